@@ -0,0 +1,156 @@
+use super::{Parameter, WeightAlgorithm};
+use crate::config::WWConfig;
+use crate::grid::{self, Grid};
+use crate::groups;
+
+use ntools::mesh::Mesh;
+use ntools::weights::WeightWindow;
+use ntools::wwgen;
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+
+/// CADIS-style weights from adjoint (importance) mesh tallies
+pub struct Cadis;
+
+impl WeightAlgorithm for Cadis {
+    fn name(&self) -> &'static str {
+        "cadis"
+    }
+
+    fn description(&self) -> &'static str {
+        "inverse of an adjoint flux, normalised at --source"
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        &[Parameter::Source]
+    }
+
+    fn generate(&self, mesh: &Mesh, cli: &WWConfig) -> Result<WeightWindow> {
+        adjoint_to_ww(mesh, cli)
+    }
+}
+
+/// Calculate weights inversely proportional to an adjoint flux mesh
+///
+/// Every weight is `R_g/φ†_g`, where `φ†_g` is the adjoint flux of the voxel in
+/// energy/time group `g`, and `R_g` is the average adjoint flux of the same
+/// group over the source voxels. Weights in the source region are therefore
+/// close to 1.0 in every group, the usual source particle weight. Voxels above
+/// the error limit, or without any adjoint flux, are analogue, as is any group
+/// without adjoint flux in the source region.
+///
+/// The source is a point (3 values) or region (6 values, lower and upper
+/// bounds for each axis) in the coordinates of the mesh, i.e. x, y, z for
+/// rectangular meshes, r, z, theta for cylindrical meshes, and r, polar,
+/// azimuthal for spherical meshes.
+pub fn adjoint_to_ww(mesh: &Mesh, cli: &WWConfig) -> Result<WeightWindow> {
+    let source = source_voxels(mesh, &cli.source)?;
+    debug!("{} voxels in the source region", source.len());
+
+    // header and group structure only, every weight is replaced below
+    let mut ww = wwgen::mesh_to_ww(mesh, 1.0, 1.0, cli.total);
+    let voxels = grid::voxel_indices(mesh, &ww, cli.total);
+    let grid = Grid::from_ww(&ww);
+
+    let groups = groups::weight_groups(mesh, cli.total);
+    let error = groups::resolve(&cli.error, &groups, "--error")?;
+
+    // response estimate of every group from its adjoint flux over the source
+    let n_spatial = mesh.iints * mesh.jints * mesh.kints;
+    let response = (0..grid.n_groups())
+        .map(|g| {
+            let offset = (voxels[g * grid.n_voxels()] / n_spatial) * n_spatial;
+            source
+                .iter()
+                .map(|s| mesh.voxels.get(offset + s).map(|v| v.result).unwrap_or(0.0))
+                .sum::<f64>()
+                / source.len() as f64
+        })
+        .collect::<Vec<f64>>();
+
+    if response.iter().all(|r| *r <= 0.0) {
+        return Err(anyhow!(
+            "No adjoint flux in the source region of mesh {}",
+            mesh.id
+        ));
+    }
+    for (group, r) in groups.iter().zip(&response) {
+        if *r > 0.0 {
+            debug!("Source response estimate for ({group}): {r:.5e}");
+        } else {
+            warn!("Warning: No adjoint flux over the source for ({group}), set to analogue");
+        }
+    }
+
+    for (idx, (weight, v)) in ww.weights.iter_mut().zip(voxels).enumerate() {
+        let group = idx / grid.n_voxels();
+        let (cutoff, response) = (error[group], response[group]);
+
+        *weight = match mesh.voxels.get(v) {
+            Some(voxel) if response > 0.0 && voxel.result > 0.0 && voxel.error <= cutoff => {
+                response / voxel.result
+            }
+            _ => 0.0,
+        };
+    }
+
+    Ok(ww)
+}
+
+/// Spatial indices of every mesh voxel in the source point or region
+fn source_voxels(mesh: &Mesh, source: &[f64]) -> Result<Vec<usize>> {
+    let ranges = match source.len() {
+        3 => [
+            (source[0], source[0]),
+            (source[1], source[1]),
+            (source[2], source[2]),
+        ],
+        6 => [
+            (source[0], source[1]),
+            (source[2], source[3]),
+            (source[4], source[5]),
+        ],
+        0 => return Err(anyhow!("Adjoint sets need a --source point or region")),
+        n => {
+            return Err(anyhow!(
+                "Expected 3 (point) or 6 (region) --source values, found {n}"
+            ))
+        }
+    };
+
+    let i = bins_within(&mesh.imesh, ranges[0]);
+    let j = bins_within(&mesh.jmesh, ranges[1]);
+    let k = bins_within(&mesh.kmesh, ranges[2]);
+
+    let mut voxels = Vec::with_capacity(i.len() * j.len() * k.len());
+    for i in &i {
+        for j in &j {
+            for k in &k {
+                voxels.push((i * mesh.jints + j) * mesh.kints + k);
+            }
+        }
+    }
+
+    if voxels.is_empty() {
+        Err(anyhow!("Source {source:?} is outside of mesh {}", mesh.id))
+    } else {
+        Ok(voxels)
+    }
+}
+
+/// Bins overlapping the (lower, upper) range, or the first containing a point
+fn bins_within(bounds: &[f64], (lower, upper): (f64, f64)) -> Vec<usize> {
+    let mut bins = bounds.windows(2).enumerate();
+
+    if lower == upper {
+        bins.find(|(_, b)| lower >= b[0] && lower <= b[1])
+            .map(|(idx, _)| vec![idx])
+            .unwrap_or_default()
+    } else {
+        let (lower, upper) = (lower.min(upper), lower.max(upper));
+        bins.filter(|(_, b)| upper > b[0] && lower < b[1])
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+}
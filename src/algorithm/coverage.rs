@@ -0,0 +1,64 @@
+use crate::grid::Grid;
+
+use ntools::weights::WeightWindow;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+
+/// Choose and apply the error cutoff of every group to meet a target coverage
+///
+/// For every energy/time group, the cutoff is the strictest relative error
+/// that still keeps the target fraction of voxels non-analogue. Voxels above
+/// the cutoff are set to analogue. Voxels without any weight can never be
+/// covered, so groups that can not reach the target keep every non-zero
+/// weight.
+///
+/// Returns the (cutoff, coverage achieved) of every group.
+pub fn cover_groups(
+    ww: &mut WeightWindow,
+    errors: &[f64],
+    coverage: f64,
+) -> Result<Vec<(f64, f64)>> {
+    if !(0.0..=1.0).contains(&coverage) {
+        return Err(anyhow!("Coverage must be a fraction from 0.0 to 1.0"));
+    }
+
+    let grid = Grid::from_ww(ww);
+    let mut cutoffs = Vec::with_capacity(grid.n_groups());
+
+    for group in 0..grid.n_groups() {
+        let start = group * grid.n_voxels();
+        let range = start..start + grid.n_voxels();
+
+        // errors of every voxel that could possibly be covered
+        let mut scored = range
+            .clone()
+            .filter(|idx| ww.weights[*idx] > 0.0)
+            .map(|idx| errors[idx])
+            .collect::<Vec<f64>>();
+        scored.sort_by(f64::total_cmp);
+
+        let needed = (coverage * grid.n_voxels() as f64).ceil() as usize;
+        let cutoff = match needed {
+            0 => 0.0,
+            n if n <= scored.len() => scored[n - 1],
+            _ => {
+                warn!("Warning: Group {group} can not reach the target coverage");
+                scored.last().copied().unwrap_or(0.0)
+            }
+        };
+
+        let mut n_covered = 0;
+        for idx in range {
+            if errors[idx] > cutoff {
+                ww.weights[idx] = 0.0;
+            } else if ww.weights[idx] > 0.0 {
+                n_covered += 1;
+            }
+        }
+
+        cutoffs.push((cutoff, n_covered as f64 / grid.n_voxels() as f64));
+    }
+
+    Ok(cutoffs)
+}
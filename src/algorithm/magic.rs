@@ -0,0 +1,44 @@
+use super::{Parameter, WeightAlgorithm};
+use crate::config::WWConfig;
+use crate::groups;
+
+use ntools::mesh::Mesh;
+use ntools::weights::WeightWindow;
+use ntools::wwgen;
+
+use anyhow::Result;
+
+/// MAGIC method weights from a forward flux mesh
+///
+/// Weights are `(0.5 * norm_flux)^power`, with flux normalised to the maximum
+/// of each energy/time group. Multiple power/error values apply to every group
+/// individually.
+pub struct Magic;
+
+impl WeightAlgorithm for Magic {
+    fn name(&self) -> &'static str {
+        "magic"
+    }
+
+    fn description(&self) -> &'static str {
+        "default, forward flux with --power de-tuning"
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        &[Parameter::Power, Parameter::AutoPower]
+    }
+
+    fn generate(&self, mesh: &Mesh, cli: &WWConfig) -> Result<WeightWindow> {
+        let groups = groups::weight_groups(mesh, cli.total);
+        let power = groups::resolve(&cli.power, &groups, "--power")?;
+        let error = groups::resolve(&cli.error, &groups, "--error")?;
+
+        // the advanced method is only needed if any group differs
+        let uniform = |values: &[f64]| values.iter().all(|v| *v == values[0]);
+        Ok(if uniform(&power) && uniform(&error) {
+            wwgen::mesh_to_ww(mesh, power[0], error[0], cli.total)
+        } else {
+            wwgen::mesh_to_ww_advanced(mesh, &power, &error)
+        })
+    }
+}
@@ -0,0 +1,145 @@
+//! Algorithms for calculating weights from a mesh tally
+//!
+//! Every algorithm implements [WeightAlgorithm] and is registered by name in
+//! [ALGORITHMS], which is how sets select one with `--method <name>`.
+
+mod cadis;
+mod coverage;
+mod magic;
+mod power;
+mod response;
+
+pub use cadis::{adjoint_to_ww, Cadis};
+pub use coverage::cover_groups;
+pub use magic::Magic;
+pub use power::{tune_power, AutoPower, PowerMetric};
+pub use response::{collapse_energy, Response, ResponseFunction, ResponseSource};
+
+use crate::config::WWConfig;
+use crate::grid;
+
+use ntools::mesh::Mesh;
+use ntools::weights::WeightWindow;
+
+use anyhow::{anyhow, Result};
+
+/// Every available algorithm, the first being the default
+pub static ALGORITHMS: &[&dyn WeightAlgorithm] = &[&Magic, &Cadis, &Response];
+
+/// Method for calculating weights from a mesh tally
+pub trait WeightAlgorithm: Sync {
+    /// Name used to select the algorithm, e.g. `--method magic`
+    fn name(&self) -> &'static str;
+
+    /// Short description for help messages
+    fn description(&self) -> &'static str;
+
+    /// Algorithm-specific set options that are accepted
+    fn parameters(&self) -> &'static [Parameter];
+
+    /// Calculate weights from a mesh using the options of a set
+    fn generate(&self, mesh: &Mesh, cli: &WWConfig) -> Result<WeightWindow>;
+
+    /// Relative errors of the data behind every weight
+    ///
+    /// Defaults to the errors of the mesh voxels, and only needs to change
+    /// for algorithms that combine mesh groups before generating weights.
+    fn errors(&self, mesh: &Mesh, cli: &WWConfig, ww: &WeightWindow) -> Result<Vec<f64>> {
+        Ok(grid::weight_errors(mesh, ww, cli.total))
+    }
+}
+
+/// Set options that only apply to some algorithms
+///
+/// Options common to every algorithm (error, total, scale, etc.) are not
+/// listed here.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Parameter {
+    /// Softening/de-tuning factor(s)
+    Power,
+    /// Automatic choice of the softening/de-tuning factor(s)
+    AutoPower,
+    /// Source point or region for normalisation
+    Source,
+    /// Energy-dependent response function
+    Response,
+}
+
+impl Parameter {
+    /// Every algorithm-specific parameter
+    pub const ALL: [Parameter; 4] = [
+        Parameter::Power,
+        Parameter::AutoPower,
+        Parameter::Source,
+        Parameter::Response,
+    ];
+
+    /// Command line option for the parameter
+    pub fn option(&self) -> &'static str {
+        match self {
+            Parameter::Power => "--power",
+            Parameter::AutoPower => "--auto-power",
+            Parameter::Source => "--source",
+            Parameter::Response => "--response",
+        }
+    }
+
+    /// Check if a set changes the parameter from its default
+    pub fn is_set(&self, cli: &WWConfig) -> bool {
+        let default = WWConfig::default();
+        match self {
+            Parameter::Power => cli.power != default.power,
+            Parameter::AutoPower => cli.auto_power != default.auto_power,
+            Parameter::Source => cli.source != default.source,
+            Parameter::Response => cli.response != default.response,
+        }
+    }
+}
+
+/// Name of every available algorithm
+pub fn names() -> Vec<&'static str> {
+    ALGORITHMS.iter().map(|a| a.name()).collect()
+}
+
+/// Find an algorithm by name
+pub fn find(name: &str) -> Result<&'static dyn WeightAlgorithm> {
+    ALGORITHMS
+        .iter()
+        .find(|a| a.name() == name)
+        .copied()
+        .ok_or_else(|| {
+            anyhow!(
+                "Unknown method \"{name}\", expected one of {}",
+                names().join(", ")
+            )
+        })
+}
+
+/// Find the algorithm for a set, checking it accepts every option given
+pub fn validate(cli: &WWConfig) -> Result<&'static dyn WeightAlgorithm> {
+    let algorithm = find(&cli.method)?;
+
+    if Parameter::Power.is_set(cli) && Parameter::AutoPower.is_set(cli) {
+        return Err(anyhow!("--power and --auto-power can not be used together"));
+    }
+
+    if cli.coverage.is_some() && cli.error != WWConfig::default().error {
+        return Err(anyhow!("--error and --coverage can not be used together"));
+    }
+
+    let unused = Parameter::ALL
+        .iter()
+        .filter(|p| p.is_set(cli) && !algorithm.parameters().contains(p))
+        .map(|p| p.option())
+        .collect::<Vec<&str>>();
+
+    if unused.is_empty() {
+        Ok(algorithm)
+    } else {
+        Err(anyhow!(
+            "{} not used by the {} method",
+            unused.join(", "),
+            algorithm.name()
+        ))
+    }
+}
@@ -0,0 +1,112 @@
+use crate::grid::Grid;
+
+use ntools::weights::WeightWindow;
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+/// Smallest power factor chosen, anything lower is practically flat
+const MIN_POWER: f64 = 0.01;
+
+/// Measure of the spread of weights to tune the power factor against
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerMetric {
+    /// Ratio of the largest to smallest non-zero weight
+    Range,
+    /// Largest ratio between face-adjacent non-zero weights
+    Ratio,
+}
+
+/// Options for choosing the power factor of every group automatically
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoPower {
+    /// Measure to tune against
+    pub metric: PowerMetric,
+    /// Target value of the measure for every group
+    pub target: f64,
+}
+
+/// Choose and apply the power factor of every group to meet a target
+///
+/// Weights must have been generated with a power of 1.0. Both metrics are
+/// ratios of weights, so applying a power `p` raises them to the same power
+/// and the factor is found directly as `ln(target) / ln(metric)`. Factors are
+/// kept between 0.01 and 1.0, so groups that already meet the target are left
+/// alone.
+///
+/// The number of analogue voxels does not depend on the power factor, so the
+/// non-analogue percentage is unchanged.
+///
+/// Returns the power factor chosen for every group.
+pub fn tune_power(ww: &mut WeightWindow, config: &AutoPower) -> Result<Vec<f64>> {
+    if config.target <= 1.0 {
+        return Err(anyhow!("Power tuning target must be greater than 1.0"));
+    }
+
+    let grid = Grid::from_ww(ww);
+    let mut powers = Vec::with_capacity(grid.n_groups());
+
+    for group in 0..grid.n_groups() {
+        let start = group * grid.n_voxels();
+        let range = start..start + grid.n_voxels();
+
+        let metric = match config.metric {
+            PowerMetric::Range => dynamic_range(&ww.weights[range.clone()]),
+            PowerMetric::Ratio => neighbour_ratio(&grid, &ww.weights, range.clone()),
+        };
+
+        let power = if metric > 1.0 {
+            (config.target.ln() / metric.ln()).clamp(MIN_POWER, 1.0)
+        } else {
+            1.0
+        };
+        debug!(
+            "Group {group}: {:?} {metric:.3e} at p=1.0, using p={power:.4}",
+            config.metric
+        );
+
+        if power == MIN_POWER {
+            warn!("Warning: Group {group} limited to a power factor of {MIN_POWER}");
+        }
+
+        for weight in &mut ww.weights[range] {
+            if *weight > 0.0 {
+                *weight = weight.powf(power);
+            }
+        }
+        powers.push(power);
+    }
+
+    Ok(powers)
+}
+
+/// Ratio of the largest to smallest non-zero weight
+fn dynamic_range(weights: &[f64]) -> f64 {
+    let (lo, hi) = weights
+        .iter()
+        .filter(|w| **w > 0.0)
+        .fold((f64::MAX, 0.0_f64), |(lo, hi), w| (lo.min(*w), hi.max(*w)));
+    if hi > 0.0 {
+        hi / lo
+    } else {
+        1.0
+    }
+}
+
+/// Largest ratio between face-adjacent non-zero weights of a group
+fn neighbour_ratio(grid: &Grid, weights: &[f64], range: std::ops::Range<usize>) -> f64 {
+    range
+        .filter(|idx| weights[*idx] > 0.0)
+        .flat_map(|idx| {
+            grid.neighbours(idx)
+                .into_iter()
+                .filter(|n| weights[*n] > 0.0)
+                .map(move |n| weights[n] / weights[idx])
+        })
+        .fold(1.0, f64::max)
+}
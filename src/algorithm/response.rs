@@ -0,0 +1,208 @@
+use super::{Magic, Parameter, WeightAlgorithm};
+use crate::config::WWConfig;
+use crate::grid;
+
+use ntools::mesh::{Mesh, Voxel};
+use ntools::weights::WeightWindow;
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// MAGIC method weights from a response-weighted collapse of the energy groups
+///
+/// Every explicit energy group is multiplied by the response function at the
+/// group midpoint and summed, giving a single energy-independent group before
+/// the usual MAGIC method is applied. Useful when optimising for dose or
+/// activation rather than flux.
+pub struct Response;
+
+impl WeightAlgorithm for Response {
+    fn name(&self) -> &'static str {
+        "response"
+    }
+
+    fn description(&self) -> &'static str {
+        "energy groups collapsed with a --response function"
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        &[Parameter::Power, Parameter::AutoPower, Parameter::Response]
+    }
+
+    fn generate(&self, mesh: &Mesh, cli: &WWConfig) -> Result<WeightWindow> {
+        Magic.generate(&collapse_response(mesh, cli)?, cli)
+    }
+
+    fn errors(&self, mesh: &Mesh, cli: &WWConfig, ww: &WeightWindow) -> Result<Vec<f64>> {
+        Ok(grid::weight_errors(
+            &collapse_response(mesh, cli)?,
+            ww,
+            cli.total,
+        ))
+    }
+}
+
+/// Collapse the energy groups using the response function of a set
+fn collapse_response(mesh: &Mesh, cli: &WWConfig) -> Result<Mesh> {
+    let source = cli
+        .response
+        .as_ref()
+        .ok_or_else(|| anyhow!("The response method needs a --response function"))?;
+    collapse_energy(mesh, &ResponseFunction::try_from(source)?)
+}
+
+/// Where to find the response function for a set
+///
+/// In a recipe this is either a path (`response = "dose.csv"`) or inline
+/// energy/value pairs (`response = [[1e-9, 3.2], [20.0, 5.6]]`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ResponseSource {
+    /// CSV file of energy (MeV), response value rows
+    File(String),
+    /// Energy (MeV), response value pairs
+    Pairs(Vec<[f64; 2]>),
+}
+
+/// Energy-dependent response, e.g. a flux-to-dose conversion table
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseFunction {
+    /// (energy, value) pairs in increasing energy order
+    pub points: Vec<(f64, f64)>,
+}
+
+impl ResponseFunction {
+    /// Build from (energy, value) pairs in any order
+    pub fn from_pairs(pairs: &[[f64; 2]]) -> Result<Self> {
+        if pairs.is_empty() {
+            return Err(anyhow!("Response function has no values"));
+        }
+
+        let mut points = pairs.iter().map(|p| (p[0], p[1])).collect::<Vec<_>>();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self { points })
+    }
+
+    /// Read energy, value rows from a CSV file
+    ///
+    /// Values may be separated by commas or whitespace. Any line that does
+    /// not start with two numbers (headers, comments) is skipped.
+    pub fn from_csv(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Unable to read response file \"{path}\": {e}"))?;
+
+        let pairs = content
+            .lines()
+            .filter_map(|line| {
+                let mut values = line
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.parse::<f64>());
+                match (values.next(), values.next()) {
+                    (Some(Ok(e)), Some(Ok(v))) => Some([e, v]),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<[f64; 2]>>();
+
+        debug!("Read {} response values from {path}", pairs.len());
+        Self::from_pairs(&pairs).map_err(|e| anyhow!("{e} in \"{path}\""))
+    }
+
+    /// Interpolate the response at an energy
+    ///
+    /// Log-log interpolation is used where both points are positive, falling
+    /// back to linear otherwise. Energies outside the table take the nearest
+    /// value.
+    pub fn evaluate(&self, energy: f64) -> f64 {
+        let first = self.points[0];
+        let last = self.points[self.points.len() - 1];
+        if energy <= first.0 {
+            return first.1;
+        } else if energy >= last.0 {
+            return last.1;
+        }
+
+        let upper = self.points.iter().position(|p| p.0 >= energy).unwrap();
+        let (e0, v0) = self.points[upper - 1];
+        let (e1, v1) = self.points[upper];
+
+        if e0 > 0.0 && v0 > 0.0 && v1 > 0.0 {
+            let f = (energy / e0).ln() / (e1 / e0).ln();
+            (v0.ln() + f * (v1 / v0).ln()).exp()
+        } else {
+            v0 + (energy - e0) / (e1 - e0) * (v1 - v0)
+        }
+    }
+}
+
+impl TryFrom<&ResponseSource> for ResponseFunction {
+    type Error = anyhow::Error;
+
+    fn try_from(source: &ResponseSource) -> Result<Self> {
+        match source {
+            ResponseSource::File(path) => Self::from_csv(path),
+            ResponseSource::Pairs(pairs) => Self::from_pairs(pairs),
+        }
+    }
+}
+
+/// Collapse the explicit energy groups of a mesh into one, weighted by response
+///
+/// Time groups are kept. Errors are propagated from every energy group,
+/// treating groups as independent, so the relative error of a voxel is
+/// `sqrt(sum((R_g * φ_g * err_g)^2)) / sum(R_g * φ_g)`.
+pub fn collapse_energy(mesh: &Mesh, response: &ResponseFunction) -> Result<Mesh> {
+    let n_groups = mesh.eints.max(1);
+    if mesh.emesh.len() != n_groups + 1 {
+        return Err(anyhow!(
+            "Mesh {} energy bounds do not match the number of groups",
+            mesh.id
+        ));
+    }
+    if n_groups == 1 {
+        warn!("Warning: Mesh {} has a single energy group", mesh.id);
+        warn!(" - The response function only scales every weight");
+    }
+
+    let factors = mesh
+        .emesh
+        .windows(2)
+        .map(|e| response.evaluate(0.5 * (e[0] + e[1])))
+        .collect::<Vec<f64>>();
+    debug!("Group response factors: {factors:?}");
+
+    let tbins = mesh.tbins();
+    let n_spatial = mesh.iints * mesh.jints * mesh.kints;
+    let mut voxels = Vec::with_capacity(tbins * n_spatial);
+
+    for t in 0..tbins {
+        for s in 0..n_spatial {
+            let (mut sum, mut variance) = (0.0, 0.0);
+            for (e, factor) in factors.iter().enumerate() {
+                let v = &mesh.voxels[(e * tbins + t) * n_spatial + s];
+                let value = factor * v.result.max(0.0);
+                sum += value;
+                variance += (value * v.error).powi(2);
+            }
+
+            voxels.push(Voxel {
+                index: voxels.len(),
+                result: sum,
+                error: if sum > 0.0 {
+                    variance.sqrt() / sum
+                } else {
+                    0.0
+                },
+            });
+        }
+    }
+
+    let mut collapsed = mesh.clone();
+    collapsed.emesh = vec![mesh.emesh[0], mesh.emesh[n_groups]];
+    collapsed.eints = 1;
+    collapsed.voxels = voxels;
+    Ok(collapsed)
+}
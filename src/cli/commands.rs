@@ -0,0 +1,174 @@
+use crate::cli::{custom_style, debug, optional};
+
+use clap::{value_parser, Arg, ArgAction, Command};
+use std::env;
+
+/// Subcommands for working with existing WWINP files
+const SUBCOMMANDS: [&str; 3] = ["split", "diff", "check"];
+
+/// Name of the subcommand if given as the first argument
+pub fn subcommand() -> Option<String> {
+    env::args()
+        .nth(1)
+        .filter(|name| SUBCOMMANDS.contains(&name.as_str()))
+}
+
+/// Initialises the Clap CLI command for a subcommand
+pub fn command_init(name: &str) -> Command {
+    match name {
+        "split" => split_init(),
+        "diff" => diff_init(),
+        "check" => check_init(),
+        _ => unreachable!("Unknown subcommand \"{name}\""),
+    }
+}
+
+fn split_init() -> Command {
+    Command::new("split")
+        .about("Split a multi-particle WWINP file into one file per particle")
+        .arg_required_else_help(true)
+        .disable_help_flag(true)
+        .long_about(
+            "Split a multi-particle WWINP file into one file per particle
+
+Every particle type in the file is written to its own WWINP file, named by particle after the output, e.g. \"wwinp_neutron\" and \"wwinp_photon\".
+
+Examples
+--------
+
+    Split into 'wwinp_neutron', 'wwinp_photon', etc...
+        $ mesh2ww split file.wwinp
+
+    Split into 'debug_neutron', etc... with a VTK for every particle
+        $ mesh2ww split file.wwinp -o debug --vtk",
+        )
+        .after_help("See --help for detail and examples")
+        .term_width(76)
+        .hide_possible_values(true)
+        .override_usage("mesh2ww split <wwinp> [options]")
+        .arg(arg_wwinp("wwinp", "Path to WWINP file"))
+        .args([
+            optional::arg_output()
+                .help_heading("File options")
+                .help("Prefix of output files ('wwinp' default)")
+                .long_help("Defaults to \"wwinp\". Every particle is written to \"<path>_<particle>\", e.g. \"wwinp_neutron\"."),
+            optional::arg_padding().help_heading("File options"),
+            optional::arg_vtk()
+                .help_heading("VTK options")
+                .long_help("Flag to specify that visual toolkit plot formats should be generated for each particle."),
+            optional::arg_format().help_heading("VTK options"),
+            optional::arg_resolution().help_heading("VTK options"),
+            optional::arg_endian().help_heading("VTK options"),
+            optional::arg_compressor().help_heading("VTK options"),
+        ])
+        .args(debug::debug_args())
+        .styles(custom_style())
+}
+
+fn diff_init() -> Command {
+    Command::new("diff")
+        .about("Compare the weights of two WWINP files")
+        .arg_required_else_help(true)
+        .disable_help_flag(true)
+        .long_about(
+            "Compare the weights of two WWINP files
+
+Every particle type in both files is compared, e.g. between iterations. The geometry and energy/time groups must match.
+
+For every energy/time group, the report gives the mean and maximum log10(new/old) of voxels non-analogue in both files. A value of 1.0 means the weights moved by an order of magnitude. Voxels that changed between analogue and non-analogue are counted separately.
+
+Examples
+--------
+
+    Report how much the weights moved
+        $ mesh2ww diff run1.wwinp run2.wwinp
+
+    Also write the ratio new/old to 'ww_neutron_ratio.vtr', etc...
+        $ mesh2ww diff run1.wwinp run2.wwinp --vtk",
+        )
+        .after_help("See --help for detail and examples")
+        .term_width(76)
+        .hide_possible_values(true)
+        .override_usage("mesh2ww diff <old> <new> [options]")
+        .args([
+            arg_wwinp("old", "Path to the old WWINP file"),
+            arg_wwinp("new", "Path to the new WWINP file"),
+        ])
+        .args([
+            optional::arg_vtk()
+                .help("Write the ratio new/old to VTK")
+                .help_heading("VTK options")
+                .long_help("Flag to specify that the ratio of new to old weights should be written to visual toolkit formats for each particle. Voxels analogue in either file are zero."),
+            optional::arg_format().help_heading("VTK options"),
+            optional::arg_resolution().help_heading("VTK options"),
+            optional::arg_endian().help_heading("VTK options"),
+            optional::arg_compressor().help_heading("VTK options"),
+        ])
+        .args(debug::debug_args())
+        .styles(custom_style())
+}
+
+fn check_init() -> Command {
+    Command::new("check")
+        .about("Check a WWINP file for problems before running MCNP")
+        .arg_required_else_help(true)
+        .disable_help_flag(true)
+        .long_about(
+            "Check a WWINP file for problems before running MCNP
+
+The file is parsed and validated for:
+    > header consistency (if, iv, nr, nwg, and padding)
+    > fine/coarse mesh counts and increasing mesh bounds
+    > increasing energy and time bounds
+    > finite, non-negative weights
+    > no values left over after the last weight
+    > the dynamic range of weights in every energy/time group
+
+Every problem is reported, and any error gives a non-zero exit code so the check can be used as a gate before submitting jobs. Warnings are reported but do not fail the check.
+
+Examples
+--------
+
+    Check a file before submitting
+        $ mesh2ww check file.wwinp && sbatch job.sh
+
+    Check a file written with --trim, allowing a larger range
+        $ mesh2ww check file.wwinp --trim --max-range 1e12",
+        )
+        .after_help("See --help for detail and examples")
+        .term_width(76)
+        .hide_possible_values(true)
+        .override_usage("mesh2ww check <wwinp> [options]")
+        .arg(arg_wwinp("wwinp", "Path to WWINP file"))
+        .args([
+            optional::arg_padding()
+                .help_heading("Check options")
+                .help("Expect no unused particles in the header")
+                .long_help("Files written with --trim should have no padding entries for unused particle types in the header. By default, padding is allowed for any particle type."),
+            arg_max_range(),
+        ])
+        .args(debug::debug_args())
+        .styles(custom_style())
+}
+
+fn arg_max_range() -> Arg {
+    Arg::new("max_range")
+        .long("max-range")
+        .help_heading("Check options")
+        .help("Largest max/min weight ratio per group")
+        .long_help("Defaults to 1e10. The ratio of the largest to smallest non-zero weight in every energy/time group must not exceed this value.")
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(f64))
+        .value_name("num")
+        .hide_default_value(true)
+}
+
+fn arg_wwinp(id: &'static str, help: &'static str) -> Arg {
+    Arg::new(id)
+        .help_heading("Arguments")
+        .help(help)
+        .required(true)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(String))
+}
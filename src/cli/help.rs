@@ -1,14 +1,16 @@
-use crate::cli::{cli_init, is_flag_present};
+use crate::cli::{cli_init, command_init, is_flag_present, subcommand};
+
+use clap::Command;
 
 pub fn help_wanted() -> bool {
     if is_flag_present(&["--help"]) {
-        let mut command = cli_init();
+        let mut command = help_command();
         command
             .print_long_help()
             .expect("Could not print help message");
         true
     } else if is_flag_present(&["-h"]) {
-        let mut command = cli_init();
+        let mut command = help_command();
         command.print_help().expect("Could not print help message");
         true
     } else {
@@ -16,6 +18,14 @@ pub fn help_wanted() -> bool {
     }
 }
 
+/// Help for the subcommand if one is given, otherwise the main command
+fn help_command() -> Command {
+    match subcommand() {
+        Some(name) => command_init(&name),
+        None => cli_init(),
+    }
+}
+
 pub fn usage_message() -> &'static str {
     "mesh2ww <meshtal> <number> [options] [+]"
 }
@@ -28,13 +38,18 @@ pub fn after_help_message() -> &'static str {
 pub fn cli_long_help() -> &'static str {
     "Conversion of meshtal file meshes to MCNP weight windows
     
-For multiple particle types, use the '+' operator to combine multiple tallies that have the same dimensions.
+For multiple particle types, use the '+' operator to combine multiple tallies that have the same dimensions. Meshes that do not match are refused before writing, with a report of every difference.
 
 Use the --vtk flag to generate Visual Toolkit files for plotting.
 
-For advanced users, the --power and --error de-tuning factors may be set for individual energy/time groups. All groups must be explicitly provided.
+For advanced users, the --power and --error de-tuning factors may be set for individual energy/time groups. Either all groups must be explicitly provided, or named specs matched against the group bounds.
 
-Supports all mesh output formats for rectangular and cylindrical geometries. 
+Supports all mesh output formats (including MCNPv6.3 COLSCI and CFSCI) for rectangular, cylindrical, and spherical geometries.
+
+Subcommands work on existing WWINP files, see `mesh2ww <subcommand> --help`:
+    > split (write one file per particle)
+    > diff (compare the weights of two files)
+    > check (validate a file before running MCNP)
 
 Typical examples 
 ----------------
@@ -45,15 +60,49 @@ Typical examples
     Change the softening/de-tuning factor  
         $ mesh2ww file.msht 14 --power 0.8 
 
+    Choose the power factor for 6 orders of magnitude
+        $ mesh2ww file.msht 14 --auto-power range --target 1e6
+
     Only generate weights for voxels with <10% error
         $ mesh2ww file.msht 14 --error 0.1
 
+    Choose error cutoffs that keep 80% of voxels non-analogue
+        $ mesh2ww file.msht 14 --coverage 0.8
+
     Only use the 'Total' energy/time groups 
         $ mesh2ww file.msht 14 --total
 
+    Collapse fine tally energy groups into 3 weight groups
+        $ mesh2ww file.msht 14 --ww-energies 0.1 1 20
+
     Multiply all weights by a constant factor
         $ mesh2ww file.msht 14 --scale 2.0
 
+    Fill analogue holes from scored neighbours
+        $ mesh2ww file.msht 14 --fill log
+
+    Smooth noisy weights with a 5x5x5 median filter
+        $ mesh2ww file.msht 14 --smooth median --kernel 2
+
+    Limit adjacent weights to within a factor of 10
+        $ mesh2ww file.msht 14 --max-ratio 10
+
+    Combine the same tally from independent runs
+        $ mesh2ww run0.msht 14 --merge run1.msht run2.msht
+        $ mesh2ww \"run_*.msht\" 14
+
+    Post-process an existing WWINP file (no tally number)
+        $ mesh2ww wwinp --smooth median --max-ratio 10
+
+    Merge with weights cached from previous runs
+        $ mesh2ww file.msht 14 --update
+
+    CADIS weights from an adjoint mesh, source at a point
+        $ mesh2ww adjoint.msht 14 --method cadis --source 0 0 0
+
+    Collapse energy groups with a flux-to-dose response
+        $ mesh2ww file.msht 14 --method response --response dose.csv
+
 
 Mutli-particle examples 
 -----------------------
@@ -66,6 +115,33 @@ Mutli-particle examples
                 + fileB 24 -p 0.5 -e 0.15       \\
                 + fileC 14 --total 
 
+    Combine several sets for the same particle:
+        $ mesh2ww fileA 14 + fileB 14 --duplicates geometric-mean
+
+    Replace only the photon weights of an existing file:
+        $ mesh2ww fileB 24 --append -o wwinp
+
+Resampling meshes
+-----------------
+
+    Resample every mesh onto the grid of the first set:
+        $ mesh2ww fine.msht 14 + coarse.msht 24 --resample 1
+
+    Resample every mesh onto an explicit grid:
+        $ mesh2ww fileA 14 + fileB 24      \\
+                --imesh -10 0 10 20        \\
+                --jmesh -10 10             \\
+                --kmesh 0 5 10
+
+Recipe files
+------------
+
+    Print the equivalent TOML recipe for a command:
+        $ mesh2ww fileA 14 -p 0.8 + fileB 24 --dump-config > job.toml
+
+    Run everything described in a recipe:
+        $ mesh2ww --config job.toml
+
 VTK plotting outputs 
 --------------------
 
@@ -97,6 +173,10 @@ Advanced de-tuning
                   --power 0.8 0.7  \\   => (e0,t0) (e0,t1)
                           0.9 0.8  \\   => (e1,t0) (e1,t1)
                           0.7 0.6  \\   => (e2,t0) (e2,t1)
+
+    Set factors by the energy bounds of each group (quoted)
+        $ mesh2ww file.msht 104    \\
+                  --power 'e<=1MeV:0.8' 'e>10MeV:0.6' default:0.7
                   
 Notes
 -----
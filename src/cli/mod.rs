@@ -1,3 +1,4 @@
+mod commands;
 mod debug;
 mod help;
 mod optional;
@@ -8,7 +9,8 @@ use clap::builder::Styles;
 use clap::Command;
 use std::env;
 
-// re-export the help function
+// re-export the help function and subcommands
+pub use commands::{command_init, subcommand};
 pub use help::help_wanted;
 
 /// Initialises the Clap CLI command and sets up arguments
@@ -1,20 +1,47 @@
-use crate::wrappers::{CliByteOrder, CliCompressor, CliVtkFormat};
 use clap::{value_parser, Arg, ArgAction};
+use mesh2ww::algorithm::{self, PowerMetric};
+use mesh2ww::duplicates::DuplicateStrategy;
+use mesh2ww::groups::GroupValue;
+use mesh2ww::postprocess::{AnalogueMode, FillMethod, SmoothFilter};
+use mesh2ww::wrappers::{CliByteOrder, CliCompressor, CliVtkFormat};
 
-pub fn optional_args() -> [Arg; 11] {
+pub fn optional_args() -> [Arg; 35] {
     [
         arg_power(),
+        arg_auto_power(),
+        arg_target(),
         arg_error(),
+        arg_coverage(),
         arg_total(),
+        arg_ww_energies(),
+        arg_ww_times(),
         arg_scale(),
+        arg_method(),
+        arg_source(),
+        arg_response(),
+        arg_merge(),
+        arg_update(),
+        arg_fill(),
+        arg_max_ratio(),
+        arg_smooth(),
+        arg_kernel(),
+        arg_strength(),
+        arg_analogue(),
         arg_output(),
         arg_padding(),
+        arg_append(),
+        arg_duplicates(),
+        arg_config(),
+        arg_dump_config(),
+        arg_resample(),
+        arg_imesh(),
+        arg_jmesh(),
+        arg_kmesh(),
         arg_vtk(),
         arg_format(),
         arg_resolution(),
         arg_endian(),
         arg_compressor(),
-        // arg_update(),
     ]
 }
 
@@ -25,18 +52,56 @@ fn arg_power() -> Arg {
             .help_heading("Weight options")
             .help("Set the softening/de-tuning factor")
             .long_help(
-                "Set the softening/de-tuning factor\n\nDefault 0.70. The softening/de-tuning factor is applied to the weights as ww => ww^(<num>).\n\nFor advanced use, multiple values are provided. These will apply to each energy/time group individually (see examples above).",
+                "Set the softening/de-tuning factor\n\nDefault 0.70. The softening/de-tuning factor is applied to the weights as ww => ww^(<num>).\n\nFor advanced use, multiple values are provided. These apply to each energy/time group individually, either as a flat list in group order or as named specs matched against the group bounds, e.g. --power e<=1MeV:0.8 e>10MeV:0.6 default:0.7 (see examples above).",
             )
             .required(false)
             .action(ArgAction::Set)
             .value_delimiter(' ')
             .num_args(1..)
-            .value_parser(value_parser!(f64))
+            .value_parser(group_value)
             .default_value("0.7")
             .value_name("num")
             .hide_default_value(true)
 }
 
+/// Parse a plain value or named spec for a group option
+fn group_value(s: &str) -> Result<GroupValue, String> {
+    s.parse::<GroupValue>().map_err(|e| e.to_string())
+}
+
+fn arg_auto_power() -> Arg {
+    Arg::new("auto-power")
+        .long("auto-power")
+        .help_heading("Weight options")
+        .help("Choose the power factor to meet a --target")
+        .long_help(
+            "Choose the power factor to meet a --target\n\nThe softening/de-tuning factor of every energy/time group is chosen so the weights meet the --target value of a metric. The chosen factors are printed as the equivalent --power option so the run can be reproduced.\n\nAvailable metrics:
+    > range (largest/smallest non-zero weight)
+    > ratio (largest ratio between adjacent weights)\n\nFactors are kept between 0.01 and 1.0. The percentage of non-analogue voxels does not depend on the power factor, see --error for that.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(PowerMetric))
+        .value_name("metric")
+        .conflicts_with("power")
+        .requires("target")
+}
+
+fn arg_target() -> Arg {
+    Arg::new("target")
+        .long("target")
+        .help_heading("Weight options")
+        .help("Target value for --auto-power")
+        .long_help(
+            "Target value for --auto-power\n\ne.g. --auto-power range --target 1e6 limits every group to 6 orders of magnitude, and --auto-power ratio --target 10 allows at most a factor of 10 between neighbours.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(f64))
+        .value_name("num")
+        .requires("auto-power")
+}
+
 fn arg_error() -> Arg {
     Arg::new("error")
             .short('e')
@@ -44,18 +109,33 @@ fn arg_error() -> Arg {
             .help_heading("Weight options")
             .help("Maximum rel. error, use analogue above")
             .long_help(
-                "Maximum rel. error, use analogue above\n\nDefault 1.0 (100%). Relative errors above the provided value are set to zero, and will continue to use analogue transport until better statistics are available.\n\nFor advanced use, multiple values are provided. These will apply to each energy/time group individually (see examples above).",
+                "Maximum rel. error, use analogue above\n\nDefault 1.0 (100%). Relative errors above the provided value are set to zero, and will continue to use analogue transport until better statistics are available.\n\nFor advanced use, multiple values are provided. These apply to each energy/time group individually, either as a flat list in group order or as named specs, e.g. --error t>1e3:0.5 default:1.0 (see examples above).",
             )
             .required(false)
             .action(ArgAction::Set)
             .value_delimiter(' ')
             .num_args(1..)
-            .value_parser(value_parser!(f64))
+            .value_parser(group_value)
             .default_value("1.0")
             .value_name("num")
             .hide_default_value(true)
 }
 
+fn arg_coverage() -> Arg {
+    Arg::new("coverage")
+        .long("coverage")
+        .help_heading("Weight options")
+        .help("Choose the error cutoff to keep a fraction of voxels")
+        .long_help(
+            "Choose the error cutoff to keep a fraction of voxels\n\nThe maximum relative error of every energy/time group is chosen as the strictest value that still keeps the given fraction of voxels non-analogue, e.g. --coverage 0.8 for 80%. The chosen cutoffs and the coverage achieved are printed for every group.\n\nVoxels that did not score can never be covered, so groups that can not reach the target keep every scored voxel.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(f64))
+        .value_name("frac")
+        .conflicts_with("error")
+}
+
 fn arg_total() -> Arg {
     Arg::new("total")
             .short('t')
@@ -69,6 +149,31 @@ fn arg_total() -> Arg {
             .action(ArgAction::SetTrue)
 }
 
+fn arg_ww_energies() -> Arg {
+    arg_ww_groups("ww-energies", "energy", "MeV")
+}
+
+fn arg_ww_times() -> Arg {
+    arg_ww_groups("ww-times", "time", "shakes")
+}
+
+fn arg_ww_groups(id: &'static str, kind: &str, unit: &str) -> Arg {
+    Arg::new(id)
+        .long(id)
+        .help_heading("Weight options")
+        .help(format!("Collapse tally {kind} groups for weights"))
+        .long_help(format!(
+            "Collapse tally {kind} groups for weights\n\nUpper {kind} bounds ({unit}) of the weight window groups, as in the WWG{} card. Tally groups are summed into the requested groups before weights are generated, with errors propagated.\n\nEvery boundary must line up with a tally bin edge, and the last must be the upper edge of the tally.",
+            kind[..1].to_uppercase()
+        ))
+        .required(false)
+        .action(ArgAction::Set)
+        .value_delimiter(' ')
+        .num_args(1..)
+        .value_parser(value_parser!(f64))
+        .value_name("num")
+}
+
 fn arg_scale() -> Arg {
     Arg::new("scale")
             .short('s')
@@ -86,7 +191,201 @@ fn arg_scale() -> Arg {
             .hide_default_value(true)
 }
 
-fn arg_output() -> Arg {
+fn arg_method() -> Arg {
+    let methods = algorithm::ALGORITHMS
+        .iter()
+        .map(|a| format!("    > {} ({})", a.name(), a.description()))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    Arg::new("method")
+        .long("method")
+        .help_heading("Weight options")
+        .help("Algorithm used to calculate weights")
+        .long_help(format!(
+            "Algorithm used to calculate weights\n\nEvery method only accepts the options it uses, e.g. --power is not used by cadis.\n\nAvailable methods:\n{methods}"
+        ))
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(algorithm::names())
+        .default_value("magic")
+        .value_name("name")
+        .hide_default_value(true)
+}
+
+fn arg_source() -> Arg {
+    Arg::new("source")
+        .long("source")
+        .help_heading("Weight options")
+        .help("Source point or region for normalisation")
+        .long_help(
+            "Source point or region for normalisation\n\nRequired by --method cadis. Either a point (3 values) or a region (6 values, i.e. lower and upper bounds for each axis) in the coordinates of the mesh. These are x y z for rectangular meshes, r z theta for cylindrical meshes, and r polar azimuthal for spherical meshes, with angles in revolutions.\n\nEvery energy/time group is normalised by its own average adjoint flux over every voxel touching the source.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_delimiter(' ')
+        .num_args(1..)
+        .allow_negative_numbers(true)
+        .value_parser(value_parser!(f64))
+        .value_name("num")
+}
+
+fn arg_response() -> Arg {
+    Arg::new("response")
+        .long("response")
+        .help_heading("Weight options")
+        .help("Response function to collapse energy groups")
+        .long_help(
+            "Response function to collapse energy groups\n\nRequired by --method response. Either a CSV file of energy (MeV), value rows, or inline pairs, e.g. --response 1e-9 3.2 1.0 5.6 20.0 8.1\n\nEvery energy group is weighted by the response at the group midpoint (log-log interpolation) and summed into a single group, with errors propagated. Use this for dose or activation rather than flux, e.g. with a flux-to-dose table.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_delimiter(' ')
+        .num_args(1..)
+        .value_parser(value_parser!(String))
+        .value_name("path|pairs")
+}
+
+fn arg_merge() -> Arg {
+    Arg::new("merge")
+        .short('m')
+        .long("merge")
+        .help_heading("Weight options")
+        .help("Combine with the same mesh from other runs")
+        .long_help(
+            "Combine with the same mesh from other runs\n\nAdditional meshtal files (or quoted glob patterns) from independent runs of the same model, e.g. with different random number seeds. The mesh tally from every file is combined by inverse-variance weighting, with errors propagated, before any weights are calculated.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_delimiter(' ')
+        .num_args(1..)
+        .value_parser(value_parser!(String))
+        .value_name("path")
+        .hide_default_value(true)
+}
+
+fn arg_update() -> Arg {
+    Arg::new("update")
+        .short('u')
+        .long("update")
+        .help_heading("Weight options")
+        .help("Update cached weights ('cached_weights' default)")
+        .long_help(
+            "Update cached weights ('cached_weights' default)\n\nEvery run writes the weights and associated errors to a cache, one per particle type (e.g. cached_weights_neutron). Later runs merge new weights into the cache, keeping whichever value has the lowest relative error for each voxel.\n\nAn optional path prefix may be provided for the cache files.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .num_args(0..=1)
+        .default_missing_value("cached_weights")
+        .value_parser(value_parser!(String))
+        .value_name("path")
+        .hide_default_value(true)
+}
+
+fn arg_fill() -> Arg {
+    Arg::new("fill")
+        .long("fill")
+        .help_heading("Weight options")
+        .help("Fill analogue holes from scored neighbours")
+        .long_help(
+            "Fill analogue holes from scored neighbours\n\nVoxels above the --error limit, or void in CuV, are normally analogue. These holes are filled from the outside in using non-zero neighbours, and estimates are kept within the range of the original weights.\n\nAvailable methods:
+    > log (geometric mean of neighbours)
+    > attenuation (extrapolate the trend along each axis)\n\nFilled voxels are flagged in a separate VTK file when using --vtk.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(FillMethod))
+        .value_name("method")
+        .hide_default_value(true)
+}
+
+fn arg_max_ratio() -> Arg {
+    Arg::new("max-ratio")
+        .long("max-ratio")
+        .help_heading("Weight options")
+        .help("Cap the ratio between adjacent weights")
+        .long_help(
+            "Cap the ratio between adjacent weights\n\nLarge jumps in weight between neighbouring voxels cause splitting storms and long histories. Any weight more than <num> times smaller than a face-adjacent neighbour is raised to match the limit, repeated until no more weights change.\n\ne.g. --max-ratio 10 allows at most a factor of 10 between neighbours. Analogue voxels are not changed.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(f64))
+        .value_name("num")
+        .hide_default_value(true)
+}
+
+fn arg_smooth() -> Arg {
+    Arg::new("smooth")
+        .long("smooth")
+        .help_heading("Smoothing options")
+        .help("Smooth noisy weights with a spatial filter")
+        .long_help(
+            "Smooth noisy weights with a spatial filter\n\nFilters are applied to log10 of the weights, separately for every energy/time group. Poorly converged voxels otherwise give speckled weights and unstable variance reduction.\n\nAvailable filters:
+    > median (median of the kernel)
+    > gaussian (sigma is half the kernel half-width)
+    > error (inverse variance weighted average)",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(SmoothFilter))
+        .value_name("filter")
+        .hide_default_value(true)
+}
+
+fn arg_kernel() -> Arg {
+    Arg::new("kernel")
+        .long("kernel")
+        .help_heading("Smoothing options")
+        .help("Kernel half-width in voxels")
+        .long_help(
+            "Kernel half-width in voxels\n\nDefault 1. Every voxel is filtered using all voxels within this many steps along each axis, e.g. --kernel 2 uses a 5x5x5 cube.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(usize))
+        .default_value("1")
+        .value_name("num")
+        .hide_default_value(true)
+        .requires("smooth")
+}
+
+fn arg_strength() -> Arg {
+    Arg::new("strength")
+        .long("strength")
+        .help_heading("Smoothing options")
+        .help("Fraction of the filtered value to use")
+        .long_help(
+            "Fraction of the filtered value to use\n\nDefault 1.0. Filtered values are blended with the original in log space, from 0.0 (unchanged) to 1.0 (fully filtered).",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(f64))
+        .default_value("1.0")
+        .value_name("num")
+        .hide_default_value(true)
+        .requires("smooth")
+}
+
+fn arg_analogue() -> Arg {
+    Arg::new("analogue")
+        .long("analogue")
+        .help_heading("Smoothing options")
+        .help("Treatment of analogue voxels")
+        .long_help(
+            "Treatment of analogue voxels when smoothing:
+    > keep (default, stay analogue and are never used)
+    > smooth (take the filtered value of non-zero neighbours)",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(AnalogueMode))
+        .default_value("keep")
+        .value_name("mode")
+        .hide_default_value(true)
+        .requires("smooth")
+}
+
+pub(super) fn arg_output() -> Arg {
     Arg::new("output")
         .short('o')
         .long("output")
@@ -102,7 +401,7 @@ fn arg_output() -> Arg {
         .hide_default_value(true)
 }
 
-fn arg_padding() -> Arg {
+pub(super) fn arg_padding() -> Arg {
     Arg::new("trim")
         .long("trim")
         .help_heading("Global file options")
@@ -112,7 +411,105 @@ fn arg_padding() -> Arg {
         .action(ArgAction::SetTrue)
 }
 
-fn arg_vtk() -> Arg {
+fn arg_append() -> Arg {
+    Arg::new("append")
+        .long("append")
+        .help_heading("Global file options")
+        .help("Add or replace particles in the existing output file")
+        .long_help("Reads the existing --output file and replaces the weights of any particle type generated here, or adds them if not already present. All other particles are kept as they are, so only the re-run meshes are needed. The mesh geometry must match the existing file. A new file is written if the output does not exist yet.")
+        .required(false)
+        .action(ArgAction::SetTrue)
+}
+
+fn arg_duplicates() -> Arg {
+    Arg::new("duplicates")
+        .long("duplicates")
+        .help_heading("Global file options")
+        .help("Combine sets with the same particle type")
+        .long_help(
+            "Strategy for sets with the same particle type, e.g. several neutron meshes aimed at different detectors. Combining weights requires the same mesh geometry and energy/time groups, and analogue voxels are only kept where every set is analogue.
+    > error (default, refuse to continue)
+    > first (keep the first set)
+    > last (keep the last set)
+    > geometric-mean (geometric mean of non-zero weights)
+    > min-weight (minimum non-zero weight)",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(DuplicateStrategy))
+        .value_name("mode")
+        .hide_default_value(true)
+}
+
+fn arg_config() -> Arg {
+    Arg::new("config")
+        .long("config")
+        .help_heading("Global file options")
+        .help("Read all sets and options from a TOML recipe")
+        .long_help(
+            "Read all weight window sets and global options from a TOML recipe file instead of the command line.\n\nEvery set is a [[set]] table with the same options as the command line (meshtal, number, power, error, total, scale, update). Global options go in the [file] and [vtk] tables. Use --dump-config to generate a recipe from an existing command.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(String))
+        .value_name("path")
+        .hide_default_value(true)
+}
+
+fn arg_dump_config() -> Arg {
+    Arg::new("dump-config")
+        .long("dump-config")
+        .help_heading("Global file options")
+        .help("Print the equivalent TOML recipe and exit")
+        .long_help("Prints the current command line to stdout as an equivalent TOML recipe, without generating any weights. Useful for keeping jobs under version control and re-running them with --config.")
+        .required(false)
+        .action(ArgAction::SetTrue)
+}
+
+fn arg_resample() -> Arg {
+    Arg::new("resample")
+        .long("resample")
+        .help_heading("Global resampling options")
+        .help("Resample all meshes onto the grid of a set")
+        .long_help(
+            "Resample all meshes onto the grid of a set\n\nSets are counted from 1 in the order given. Every other mesh has its flux volume-averaged onto the voxels of the reference mesh before weights are generated, with errors propagated.\n\nAll meshes must share the same geometry type, origin, and orientation.",
+        )
+        .required(false)
+        .action(ArgAction::Set)
+        .value_parser(value_parser!(usize))
+        .value_name("set")
+        .conflicts_with_all(["imesh", "jmesh", "kmesh"])
+}
+
+fn arg_imesh() -> Arg {
+    arg_bounds("imesh", "i")
+}
+
+fn arg_jmesh() -> Arg {
+    arg_bounds("jmesh", "j")
+}
+
+fn arg_kmesh() -> Arg {
+    arg_bounds("kmesh", "k")
+}
+
+fn arg_bounds(id: &'static str, axis: &str) -> Arg {
+    Arg::new(id)
+        .long(id)
+        .help_heading("Global resampling options")
+        .help(format!("Explicit {axis} bounds of the common grid"))
+        .long_help(format!(
+            "Explicit {axis} bounds of the common grid\n\nAll bounds must be given, including the lower edge. Used with the other explicit bounds to resample every mesh onto a grid that need not match any set. Cylindrical and spherical angles are in revolutions, as in the FMESH card."
+        ))
+        .required(false)
+        .action(ArgAction::Set)
+        .value_delimiter(' ')
+        .num_args(1..)
+        .value_parser(value_parser!(f64))
+        .value_name("num")
+}
+
+pub(super) fn arg_vtk() -> Arg {
     Arg::new("vtk")
         .long("vtk")
         .help_heading("Global VTK options")
@@ -122,14 +519,14 @@ fn arg_vtk() -> Arg {
         .action(ArgAction::SetTrue)
 }
 
-fn arg_resolution() -> Arg {
+pub(super) fn arg_resolution() -> Arg {
     Arg::new("resolution")
         .short('r')
         .long("resolution")
         .help_heading("Global VTK options")
-        .help("Cylindrical mesh resolution")
+        .help("Cylindrical/spherical mesh resolution")
         .long_help(
-            "WARNING: Every vertex is defined explicitly, so large values will significantly increase memory usage and file size.\n\nInteger value for increasing angular resolution of cylindrical meshes. Cylinders are approximated to straight edge segments so it can be useful to round this off by splitting voxels into multiple smaller segments.\n\ne.g. 4 theta bins gives 4 edges and therefore looks square. Using `--resolution 3` generates 12 edges instead and looks more rounded.",
+            "WARNING: Every vertex is defined explicitly, so large values will significantly increase memory usage and file size.\n\nInteger value for increasing angular resolution of cylindrical and spherical meshes. Curved surfaces are approximated to straight edge segments so it can be useful to round this off by splitting voxels into multiple smaller segments.\n\ne.g. 4 theta bins gives 4 edges and therefore looks square. Using `--resolution 3` generates 12 edges instead and looks more rounded.",
         )
         .required(false)
         .action(ArgAction::Set)
@@ -138,7 +535,7 @@ fn arg_resolution() -> Arg {
         .hide_default_value(true)
 }
 
-fn arg_format() -> Arg {
+pub(super) fn arg_format() -> Arg {
     Arg::new("format")
         .short('f')
         .long("format")
@@ -157,7 +554,7 @@ fn arg_format() -> Arg {
         .hide_default_value(true)
 }
 
-fn arg_endian() -> Arg {
+pub(super) fn arg_endian() -> Arg {
     Arg::new("endian")
         .long("endian")
         .help_heading("Global VTK options")
@@ -174,7 +571,7 @@ fn arg_endian() -> Arg {
         .hide_default_value(true)
 }
 
-fn arg_compressor() -> Arg {
+pub(super) fn arg_compressor() -> Arg {
     Arg::new("compressor")
         .long("compressor")
         .help_heading("Global VTK options")
@@ -192,19 +589,3 @@ fn arg_compressor() -> Arg {
         .value_name("cmp")
         .hide_default_value(true)
 }
-
-// fn arg_update() -> Arg {
-//     Arg::new("update")
-//         .short('u')
-//         .long("update")
-//         .help_heading("Weight options")
-//         .help("Path to previous weights ('cached_weights' default)")
-//         .long_help(
-//             "Defaults to \"cached_weights\". Contains weights and associated errors for updating better weight values in iterative runs.",
-//         )
-//         .required(false)
-//         .action(ArgAction::Set)
-//         .value_parser(value_parser!(String))
-//         .value_name("path")
-//         .hide_default_value(true)
-// }
@@ -8,6 +8,7 @@ fn arg_meshtal() -> Arg {
     Arg::new("meshtal")
         .help_heading("Arguments")
         .help("Path to meshtal file")
+        .long_help("Path to meshtal file, or a quoted glob pattern (e.g. \"run_*.msht\") to combine several runs.\n\nAn existing WWINP file may be given instead, without a <number>, to post-process every particle type in it.")
         .action(ArgAction::Set)
         .value_parser(value_parser!(String))
 }
@@ -16,7 +17,7 @@ fn arg_number() -> Arg {
     Arg::new("number")
         .help_heading("Arguments")
         .help("Mesh tally identifier")
-        .long_help("e.g. 104 for the FMESH104:n card")
+        .long_help("e.g. 104 for the FMESH104:n card. Not needed for a WWINP input.")
         .value_parser(value_parser!(u32))
         .action(ArgAction::Set)
 }
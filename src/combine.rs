@@ -0,0 +1,218 @@
+//! Statistical combination of mesh tallies from independent runs
+
+use crate::config::WWConfig;
+
+use ntools::mesh::{Mesh, Voxel};
+
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+/// Every meshtal file for a set, with any glob patterns expanded
+pub fn meshtal_paths(cli: &WWConfig) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for pattern in std::iter::once(&cli.meshtal).chain(&cli.merge) {
+        if !is_glob(pattern) {
+            paths.push(PathBuf::from(pattern));
+            continue;
+        }
+
+        let mut matches = glob::glob(pattern)?.collect::<Result<Vec<PathBuf>, _>>()?;
+        if matches.is_empty() {
+            return Err(anyhow!("No meshtal files match \"{pattern}\""));
+        }
+        matches.sort();
+        paths.append(&mut matches);
+    }
+
+    Ok(paths)
+}
+
+/// Check for any glob special characters
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Combine the same mesh tally from independent runs
+///
+/// Every voxel is the inverse-variance weighted mean of the runs, with the
+/// error propagated as `1/sqrt(sum(1/variance))`.
+///
+/// A run that did not score in a voxel has no variance estimate, but is still
+/// evidence of a low flux. Dropping it would overstate the flux, so any voxel
+/// where a run has no variance falls back to the plain mean of every run, with
+/// the error propagated as `sqrt(sum(variance))/n`. This assumes runs of similar
+/// length, as for copies of the same input with different random number seeds.
+pub fn combine_meshes(meshes: Vec<Mesh>) -> Result<Mesh> {
+    let mut meshes = meshes.into_iter();
+    let mut combined = meshes
+        .next()
+        .ok_or_else(|| anyhow!("No meshes to combine"))?;
+
+    let mut sums = vec![VoxelSums::default(); combined.voxels.len()];
+    for (sum, voxel) in sums.iter_mut().zip(&combined.voxels) {
+        sum.add(voxel);
+    }
+
+    let mut n_runs = 1;
+    for mesh in meshes {
+        if !same_structure(&combined, &mesh) {
+            return Err(anyhow!(
+                "Mesh {} bounds differ between meshtal files, unable to combine",
+                mesh.id
+            ));
+        }
+
+        for (sum, voxel) in sums.iter_mut().zip(&mesh.voxels) {
+            sum.add(voxel);
+        }
+        n_runs += 1;
+    }
+
+    for (voxel, sum) in combined.voxels.iter_mut().zip(sums) {
+        let (result, error) = sum.combine(n_runs);
+        *voxel = Voxel {
+            index: voxel.index,
+            result,
+            error,
+        };
+    }
+
+    Ok(combined)
+}
+
+/// Running sums for a single voxel over every run
+#[derive(Debug, Default, Clone, Copy)]
+struct VoxelSums {
+    /// Sum of inverse variances
+    weight: f64,
+    /// Sum of inverse-variance weighted results
+    weighted_result: f64,
+    /// Sum of results
+    result: f64,
+    /// Sum of variances
+    variance: f64,
+    /// Any run without a variance estimate
+    unscored: bool,
+}
+
+impl VoxelSums {
+    fn add(&mut self, voxel: &Voxel) {
+        let sigma = voxel.result * voxel.error;
+        if voxel.result > 0.0 && sigma > 0.0 {
+            let w = 1.0 / (sigma * sigma);
+            self.weight += w;
+            self.weighted_result += w * voxel.result;
+            self.variance += sigma * sigma;
+        } else {
+            self.unscored = true;
+        }
+        self.result += voxel.result.max(0.0);
+    }
+
+    /// Combined (result, relative error), zero if nothing scored
+    fn combine(&self, n_runs: usize) -> (f64, f64) {
+        if self.weight <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        if self.unscored {
+            let n = n_runs as f64;
+            let result = self.result / n;
+            (result, self.variance.sqrt() / (n * result))
+        } else {
+            let result = self.weighted_result / self.weight;
+            (result, 1.0 / (self.weight.sqrt() * result))
+        }
+    }
+}
+
+fn same_structure(a: &Mesh, b: &Mesh) -> bool {
+    a.geometry == b.geometry
+        && a.particle == b.particle
+        && a.imesh == b.imesh
+        && a.jmesh == b.jmesh
+        && a.kmesh == b.kmesh
+        && a.emesh == b.emesh
+        && a.tmesh == b.tmesh
+        && a.voxels.len() == b.voxels.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Single voxel mesh with a (result, relative error) pair
+    fn mesh(result: f64, error: f64) -> Mesh {
+        Mesh {
+            id: 14,
+            imesh: vec![0.0, 1.0],
+            iints: 1,
+            jmesh: vec![0.0, 1.0],
+            jints: 1,
+            kmesh: vec![0.0, 1.0],
+            kints: 1,
+            voxels: vec![Voxel {
+                index: 0,
+                result,
+                error,
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn combine(runs: &[(f64, f64)]) -> (f64, f64) {
+        let meshes = runs.iter().map(|(r, e)| mesh(*r, *e)).collect();
+        let voxel = &combine_meshes(meshes).unwrap().voxels[0];
+        (voxel.result, voxel.error)
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-12, "{a} != {b}");
+    }
+
+    #[test]
+    fn combine_all_scored() {
+        // both runs have sigma = 1, so equal inverse-variance weights
+        let (result, error) = combine(&[(10.0, 0.1), (20.0, 0.05)]);
+        assert_close(result, 15.0);
+        assert_close(error, 1.0 / (2.0_f64.sqrt() * 15.0));
+    }
+
+    #[test]
+    fn combine_unequal_variance() {
+        // sigma = 1 and 2, so weights of 1 and 1/4
+        let (result, error) = combine(&[(10.0, 0.1), (20.0, 0.1)]);
+        assert_close(result, 12.0);
+        assert_close(error, 1.0 / (1.25_f64.sqrt() * 12.0));
+    }
+
+    #[test]
+    fn combine_partly_scored() {
+        // falls back to the plain mean, sqrt(sum(variance))/n
+        let (result, error) = combine(&[(10.0, 0.1), (0.0, 0.0)]);
+        assert_close(result, 5.0);
+        assert_close(error, 1.0 / (2.0 * 5.0));
+    }
+
+    #[test]
+    fn combine_nothing_scored() {
+        assert_eq!(combine(&[(0.0, 0.0), (0.0, 0.0)]), (0.0, 0.0));
+    }
+
+    #[test]
+    fn combine_different_bounds() {
+        let mut other = mesh(1.0, 0.1);
+        other.imesh = vec![0.0, 2.0];
+        let error = combine_meshes(vec![mesh(1.0, 0.1), other]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Mesh 14 bounds differ between meshtal files, unable to combine"
+        );
+    }
+
+    #[test]
+    fn combine_nothing() {
+        assert!(combine_meshes(vec![]).is_err());
+    }
+}
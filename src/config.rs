@@ -0,0 +1,221 @@
+//! Configuration for weight window generation and output files
+
+use crate::algorithm::{AutoPower, ResponseSource};
+use crate::duplicates::DuplicateStrategy;
+use crate::groups::GroupValue;
+use crate::postprocess::{FillMethod, SmoothConfig};
+use crate::wrappers::{CliByteOrder, CliCompressor, CliVtkFormat};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Complete description of a job, typically kept in a TOML recipe file
+///
+/// ```toml
+/// [file]
+/// output = "wwinp"
+///
+/// [vtk]
+/// vtk = true
+///
+/// [[set]]
+/// meshtal = "NP_tallies.msht"
+/// number = 14
+/// power = [0.8]
+///
+/// [[set]]
+/// meshtal = "NP_tallies.msht"
+/// number = 24
+/// total = true
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Recipe {
+    /// Strategy for combining sets with the same particle type
+    pub duplicates: DuplicateStrategy,
+    /// Global WWINP file options
+    pub file: FileConfig,
+    /// Global VTK options
+    pub vtk: VtkConfig,
+    /// Global resampling options
+    pub resample: ResampleConfig,
+    /// Weight window sets, one per particle type
+    #[serde(rename = "set")]
+    pub sets: Vec<WWConfig>,
+}
+
+impl Recipe {
+    /// Read a recipe from a TOML file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Unable to read \"{}\": {e}", path.display()))?;
+        toml::from_str(&content).map_err(|e| anyhow!("Invalid recipe \"{}\": {e}", path.display()))
+    }
+
+    /// Write the recipe out as a TOML string
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+/// Options for generating a single set of weights from a mesh tally
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WWConfig {
+    /// Path to the meshtal file, or a glob pattern for several
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub meshtal: String,
+    /// Existing WWINP file to post-process instead of a mesh tally, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wwinp: Option<String>,
+    /// Additional meshtal files (or glob patterns) to combine statistically
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub merge: Vec<String>,
+    /// Mesh tally identifier, e.g. 104 for FMESH104
+    #[serde(skip_serializing_if = "is_zero")]
+    pub number: u32,
+    /// Softening/de-tuning factor(s), one, one per energy/time group, or named
+    pub power: Vec<GroupValue>,
+    /// Automatic choice of the power factor of every group, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_power: Option<AutoPower>,
+    /// Maximum relative error(s), one, one per energy/time group, or named
+    pub error: Vec<GroupValue>,
+    /// Fraction of voxels to keep non-analogue when choosing error cutoffs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<f64>,
+    /// Only use the 'Total' energy/time groups
+    pub total: bool,
+    /// Upper energy bounds of the weight window groups, if not the tally's
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ww_energies: Vec<f64>,
+    /// Upper time bounds of the weight window groups, if not the tally's
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ww_times: Vec<f64>,
+    /// Constant multiplier for all weights
+    pub scale: f64,
+    /// Prefix for cached weights to update, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update: Option<String>,
+    /// Method for filling analogue holes, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill: Option<FillMethod>,
+    /// Maximum ratio between face-adjacent weights, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_ratio: Option<f64>,
+    /// Smoothing filter for noisy weights, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smooth: Option<SmoothConfig>,
+    /// Name of the algorithm used to calculate weights
+    pub method: String,
+    /// Source point (x y z) or region (6 values) for adjoint sets
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub source: Vec<f64>,
+    /// Response function for collapsing energy groups, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<ResponseSource>,
+}
+
+impl Default for WWConfig {
+    fn default() -> Self {
+        Self {
+            meshtal: String::new(),
+            wwinp: None,
+            merge: Vec::new(),
+            number: 0,
+            power: vec![0.7.into()],
+            auto_power: None,
+            error: vec![1.0.into()],
+            coverage: None,
+            total: false,
+            ww_energies: Vec::new(),
+            ww_times: Vec::new(),
+            scale: 1.0,
+            update: None,
+            fill: None,
+            max_ratio: None,
+            smooth: None,
+            method: "magic".to_string(),
+            source: Vec::new(),
+            response: None,
+        }
+    }
+}
+
+fn is_zero(number: &u32) -> bool {
+    *number == 0
+}
+
+/// Options for writing weight windows to VTK files for plotting
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VtkConfig {
+    /// Write a VTK file for every weight window set
+    pub vtk: bool,
+    /// VTK file format
+    pub format: CliVtkFormat,
+    /// Compression method for XML formats
+    pub compressor: CliCompressor,
+    /// Byte ordering for binary formats
+    pub endian: CliByteOrder,
+    /// Angular resolution of cylindrical and spherical meshes
+    pub resolution: u8,
+}
+
+impl Default for VtkConfig {
+    fn default() -> Self {
+        Self {
+            vtk: false,
+            format: CliVtkFormat::Xml,
+            compressor: CliCompressor::LZMA,
+            endian: CliByteOrder::BigEndian,
+            resolution: 1,
+        }
+    }
+}
+
+/// Options for resampling every mesh onto a common grid
+///
+/// Either a reference set is chosen, or explicit i/j/k bounds are given. No
+/// resampling is done by default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResampleConfig {
+    /// Set to use as the common grid, counting from 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<usize>,
+    /// Explicit i bounds of the common grid
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub imesh: Vec<f64>,
+    /// Explicit j bounds of the common grid
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub jmesh: Vec<f64>,
+    /// Explicit k bounds of the common grid
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub kmesh: Vec<f64>,
+}
+
+/// Options for writing the WWINP file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    /// Exclude unused particles from the header padding
+    pub trim: bool,
+    /// Path to the output file
+    pub output: String,
+    /// Add or replace particles in the existing output file
+    pub append: bool,
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        Self {
+            trim: false,
+            output: "wwinp".to_string(),
+            append: false,
+        }
+    }
+}
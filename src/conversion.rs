@@ -1,66 +1,180 @@
-use crate::logging;
-use crate::parser::{self, VtkConfig, WWConfig};
+//! Conversion of mesh tallies to weight windows and output files
+
+use crate::algorithm;
+use crate::combine;
+use crate::config::{FileConfig, Recipe, ResampleConfig, VtkConfig, WWConfig};
+use crate::diff::{self, GroupDiff};
+use crate::duplicates;
+use crate::geometry;
+use crate::postprocess;
+use crate::rebin;
+use crate::resample;
+use crate::update;
 use crate::wrappers::{CliByteOrder, CliCompressor, CliVtkFormat};
+use crate::wwinp;
 
 use ntools::mesh::reader::MeshtalReader;
-use ntools::mesh::{Mesh, Particle};
+use ntools::mesh::{Geometry, Mesh, Particle};
 use ntools::utils::f;
 use ntools::weights::vtk::{write_vtk, WeightsToVtk, WeightsToVtkBuilder};
-use ntools::weights::WeightWindow;
-use ntools::wwgen;
+use ntools::weights::{write_multi_particle, WeightWindow};
 
 use vtkio::model::ByteOrder;
 use vtkio::xml::Compressor;
 
 use anyhow::{anyhow, Result};
-use log::{debug, info, warn};
+use log::{debug, info, LevelFilter};
 use std::path::Path;
 
-pub fn collect_weight_windows(ww_config_sets: Vec<WWConfig>) -> Result<Vec<WeightWindow>> {
-    // prepare for writing to VTK files if needed
-    let vtk_config = parser::vtk_config();
+/// Generate weight windows for every set and write the WWINP file
+///
+/// With [FileConfig::append], particles already in the output file are kept
+/// unless replaced, and every particle written is returned.
+pub fn convert(recipe: &Recipe) -> Result<Vec<WeightWindow>> {
+    let mut weight_windows = collect_weight_windows(recipe)?;
+    if recipe.file.append {
+        weight_windows = wwinp::append_to_file(weight_windows, &recipe.file.output)?;
+    }
+    write_weight_windows(&weight_windows, &recipe.file)?;
+    Ok(weight_windows)
+}
+
+/// Write weight windows for one or more particles to a WWINP file
+///
+/// Fails without writing anything if the mesh geometries do not match.
+pub fn write_weight_windows(
+    weight_windows: &[WeightWindow],
+    file_config: &FileConfig,
+) -> Result<()> {
+    debug!("Checking mesh geometries match");
+    geometry::check_compatibility(weight_windows)?;
+
+    info!("Writing WWINP file");
+    debug!("Ouput file: \"{}\"", file_config.output);
+    write_multi_particle(weight_windows, &file_config.output, !file_config.trim);
+    Ok(())
+}
+
+/// Split a multi-particle WWINP file into one file per particle
+///
+/// Files are named by particle type after the output, e.g. "wwinp_neutron",
+/// and a VTK file is written for every particle if requested. Returns the
+/// paths of every WWINP file written.
+pub fn split_wwinp(
+    path: impl AsRef<Path>,
+    file_config: &FileConfig,
+    vtk_config: &VtkConfig,
+) -> Result<Vec<String>> {
+    let weight_windows = wwinp::read_wwinp(path)?;
+    let mut outputs = Vec::with_capacity(weight_windows.len());
+
+    for ww in &weight_windows {
+        let particle = Particle::from_id(ww.particle);
+        let output = f!(
+            "{}_{}",
+            file_config.output,
+            f!("{particle:?}").to_lowercase()
+        );
+
+        info!("Writing {particle:?} weights to \"{output}\"");
+        write_multi_particle(std::slice::from_ref(ww), &output, !file_config.trim);
+
+        if vtk_config.vtk {
+            generate_vtk(ww, vtk_config)?;
+        }
+        outputs.push(output);
+    }
+
+    Ok(outputs)
+}
+
+/// Compare the weights of two WWINP files, e.g. between iterations
+///
+/// Every particle type in both files is compared group by group, and the
+/// ratio of new to old weights is written to VTK for every particle if
+/// requested.
+pub fn diff_wwinp(
+    old: impl AsRef<Path>,
+    new: impl AsRef<Path>,
+    vtk_config: &VtkConfig,
+) -> Result<Vec<GroupDiff>> {
+    let old = wwinp::read_wwinp(old)?;
+    let new = wwinp::read_wwinp(new)?;
+    let diffs = diff::diff_weight_windows(&old, &new)?;
+
+    if vtk_config.vtk {
+        for a in &old {
+            if let Some(b) = new.iter().find(|b| b.particle == a.particle) {
+                generate_ratio_vtk(&diff::ratio_weight_window(a, b), vtk_config)?;
+            }
+        }
+    }
+
+    Ok(diffs)
+}
+
+/// Generate weight windows for every set, writing VTK files if requested
+///
+/// Sets with a WWINP input skip straight to post-processing, and every
+/// particle type in the file is used.
+pub fn collect_weight_windows(recipe: &Recipe) -> Result<Vec<WeightWindow>> {
+    let vtk_config = &recipe.vtk;
+
+    // read mesh data from every meshtal file, on the weight window groups
+    let mut meshes = recipe
+        .sets
+        .iter()
+        .filter(|cli| cli.wwinp.is_none())
+        .map(|cli| rebin::rebin_mesh(try_meshtal_read(cli)?, cli))
+        .collect::<Result<Vec<Mesh>>>()?;
+
+    // put every mesh onto a common grid if requested
+    resample::resample_meshes(&mut meshes, &mesh_resample_config(recipe)?)?;
 
     // prepare the ultimate return value
-    let mut weight_windows: Vec<WeightWindow> = Vec::with_capacity(ww_config_sets.len());
+    let mut weight_windows: Vec<WeightWindow> = Vec::with_capacity(recipe.sets.len());
+    let mut meshes = meshes.iter();
 
     // Process each weight window set
-    for cli in &ww_config_sets {
-        // read mesh data from the meshtal file
-        info!("Reading mesh {} from {}", &cli.number, &cli.meshtal);
-        let mesh = try_meshtal_read(cli)?;
-
-        // make sure the particle type is not a duplicate
-        if weight_windows
-            .iter()
-            .any(|ww| ww.particle == mesh.particle as u8)
-        {
-            info!("{:?} already included, skipping...", mesh.particle);
+    for cli in &recipe.sets {
+        if let Some(path) = &cli.wwinp {
+            wwinp::validate_wwinp_set(cli)?;
+            info!("Reading weights from {path}");
+            for mut ww in wwinp::read_wwinp(path)? {
+                let errors = vec![1.0; ww.weights.len()];
+                postprocess_weights(&mut ww, &errors, cli, vtk_config)?;
+                weight_windows.push(ww);
+            }
             continue;
         }
 
+        // fine to unwrap, there is a mesh for every set without a WWINP
+        let mesh = meshes.next().unwrap();
+
         // convert mesh into WWMesh object for writing/further manipulation
         info!("Calculating {:?} weights", &mesh.particle);
-        let mut ww = generate_weight_window(&mesh, cli);
+        let mut ww = generate_weight_window(mesh, cli)?;
+        let mut errors = algorithm::find(&cli.method)?.errors(mesh, cli, &ww)?;
 
-        // Multiply weights by a constant factor if one is provided
-        if cli.scale != 1.0 {
-            info!("Scaling results by {}", cli.scale);
-            ww.scale(cli.scale);
+        // Merge with weights cached from previous runs if requested
+        if let Some(prefix) = &cli.update {
+            info!("Updating cached {:?} weights", &mesh.particle);
+            errors = update::update_weights(&mut ww, &errors, prefix)?;
         }
 
-        info!(
-            "{:?} voxels with non-zero weight: {:.2}%",
-            Particle::from_id(ww.particle),
-            ww.non_analogue_percentage()
-        );
+        postprocess_weights(&mut ww, &errors, cli, vtk_config)?;
+        weight_windows.push(ww);
+    }
 
-        // Write this out to a VTK for plotting is needed
-        if vtk_config.vtk {
+    // make sure there is only one set per particle type
+    let weight_windows = duplicates::resolve_duplicates(weight_windows, recipe.duplicates)?;
+
+    // Write these out to VTK for plotting if needed
+    if vtk_config.vtk {
+        for ww in &weight_windows {
             info!("Writing {:?} VTK file", Particle::from_id(ww.particle));
-            generate_vtk(&ww, &vtk_config)?;
+            generate_vtk(ww, vtk_config)?;
         }
-
-        weight_windows.push(ww);
     }
 
     if weight_windows.is_empty() {
@@ -70,35 +184,240 @@ pub fn collect_weight_windows(ww_config_sets: Vec<WWConfig>) -> Result<Vec<Weigh
     }
 }
 
-fn try_meshtal_read(cli: &WWConfig) -> Result<Mesh> {
-    let path: &Path = Path::new(&cli.meshtal);
+/// Fill, smooth, limit, and scale weights using the options of a set
+///
+/// The relative errors behind every weight are only used for smoothing.
+pub fn postprocess_weights(
+    ww: &mut WeightWindow,
+    errors: &[f64],
+    cli: &WWConfig,
+    vtk_config: &VtkConfig,
+) -> Result<()> {
+    // Estimate weights for analogue holes if requested
+    let filled = cli.fill.map(|method| {
+        let filled = postprocess::fill_holes(ww, method);
+        let n_filled = filled.iter().filter(|f| **f).count();
+        info!("Filled {n_filled} analogue voxels using {method:?} estimates");
+        filled
+    });
+
+    // Filled voxels were never scored, so treat them as completely unconverged
+    let errors = match &filled {
+        Some(filled) => errors
+            .iter()
+            .zip(filled)
+            .map(|(e, f)| if *f { 1.0 } else { *e })
+            .collect(),
+        None => errors.to_vec(),
+    };
+
+    // Smooth out noisy weights if requested
+    if let Some(smooth) = &cli.smooth {
+        let n_changed = postprocess::smooth_weights(ww, &errors, smooth);
+        info!(
+            "Smoothed {n_changed} voxels with a {:?} filter",
+            smooth.filter
+        );
+    }
+
+    // Cap the jump in weight between adjacent voxels if requested
+    if let Some(max_ratio) = cli.max_ratio {
+        if max_ratio < 1.0 {
+            return Err(anyhow!("Neighbour ratio limit must be at least 1.0"));
+        }
+        let n_changed = postprocess::limit_neighbour_ratio(ww, max_ratio);
+        info!("Limited {n_changed} voxels to a neighbour ratio of {max_ratio}");
+    }
+
+    // Multiply weights by a constant factor if one is provided
+    if cli.scale != 1.0 {
+        info!("Scaling results by {}", cli.scale);
+        ww.scale(cli.scale);
+    }
+
+    info!(
+        "{:?} voxels with non-zero weight: {:.2}%",
+        Particle::from_id(ww.particle),
+        ww.non_analogue_percentage()
+    );
+
+    // Flag any filled voxels for plotting if needed
+    if let (true, Some(filled)) = (vtk_config.vtk, &filled) {
+        generate_fill_vtk(ww, filled, vtk_config)?;
+    }
+
+    Ok(())
+}
+
+/// Resampling options with the reference set counted among mesh sets only
+fn mesh_resample_config(recipe: &Recipe) -> Result<ResampleConfig> {
+    let mut config = recipe.resample.clone();
+
+    // out of range references are left for resampling to report
+    if let Some(n) = config
+        .reference
+        .filter(|n| (1..=recipe.sets.len()).contains(n))
+    {
+        if recipe.sets[n - 1].wwinp.is_some() {
+            return Err(anyhow!(
+                "Unable to resample onto set {n}, a WWINP has no mesh"
+            ));
+        }
+        let sets = &recipe.sets[..n];
+        config.reference = Some(sets.iter().filter(|cli| cli.wwinp.is_none()).count());
+    }
+
+    Ok(config)
+}
+
+/// Read the mesh tally for a set, combining every meshtal file if several
+pub fn try_meshtal_read(cli: &WWConfig) -> Result<Mesh> {
+    let paths = combine::meshtal_paths(cli)?;
 
+    let mut meshes = paths
+        .iter()
+        .map(|path| {
+            info!("Reading mesh {} from {}", &cli.number, path.display());
+            read_mesh(path, cli.number)
+        })
+        .collect::<Result<Vec<Mesh>>>()?;
+
+    if meshes.len() > 1 {
+        info!(
+            "Combining {} meshes by inverse-variance weighting",
+            meshes.len()
+        );
+        combine::combine_meshes(meshes)
+    } else {
+        Ok(meshes.remove(0))
+    }
+}
+
+fn read_mesh(path: &Path, number: u32) -> Result<Mesh> {
+    // no progress bars when quiet, tracing, or without a logger at all
     let mut reader = MeshtalReader::new();
-    reader.set_target_id(cli.number);
-    if logging::is_quiet() || logging::verbosity() > 1 {
+    reader.set_target_id(number);
+    if matches!(log::max_level(), LevelFilter::Off | LevelFilter::Trace) {
         reader.disable_progress();
     }
 
+    // output format (COL, CF, COLSCI, CFSCI, etc.) is detected by the reader
     let mut mesh = reader.parse(path)?;
+    debug!("Detected {:?} output format", mesh[0].format);
     Ok(std::mem::take(&mut mesh[0]))
 }
 
-fn generate_weight_window(mesh: &Mesh, cli: &WWConfig) -> WeightWindow {
-    if cli.power.len() > 1 || cli.error.len() > 1 {
-        if cli.total {
-            warn!("Warning: Conflicting options");
-            warn!(" - Multiple --power/--error values used with --total");
-            warn!(" - Falling back to default values (p=0.7, e=1.0)");
-            wwgen::mesh_to_ww(mesh, 0.7, 1.0, cli.total)
-        } else {
-            wwgen::mesh_to_ww_advanced(mesh, &cli.power, &cli.error)
+/// Calculate weights from a mesh using the algorithm chosen for a set
+///
+/// Any error cutoffs or power factors chosen automatically are applied after
+/// generating weights with every voxel kept and no de-tuning.
+pub fn generate_weight_window(mesh: &Mesh, cli: &WWConfig) -> Result<WeightWindow> {
+    let algorithm = algorithm::validate(cli)?;
+    debug!("Using the {} method", algorithm.name());
+
+    let untuned = WWConfig {
+        power: match cli.auto_power {
+            Some(_) => vec![1.0.into()],
+            None => cli.power.clone(),
+        },
+        error: match cli.coverage {
+            Some(_) => vec![1.0.into()],
+            None => cli.error.clone(),
+        },
+        ..cli.clone()
+    };
+    let mut ww = algorithm.generate(mesh, &untuned)?;
+    check_header(mesh, &ww)?;
+
+    // choose the error cutoff of every group for a target coverage
+    if let Some(coverage) = cli.coverage {
+        let errors = algorithm.errors(mesh, cli, &ww)?;
+        let cutoffs = algorithm::cover_groups(&mut ww, &errors, coverage)?;
+        for (group, (cutoff, covered)) in cutoffs.iter().enumerate() {
+            info!(
+                "Group {group}: error cutoff {cutoff:.4}, {:.2}% coverage",
+                100.0 * covered
+            );
         }
-    } else {
-        wwgen::mesh_to_ww(mesh, cli.power[0], cli.error[0], cli.total)
+        info!(
+            "Chosen for {:.2}% coverage, equivalent to --error {}",
+            100.0 * coverage,
+            join_values(cutoffs.iter().map(|c| c.0))
+        );
+    }
+
+    // choose the power factor of every group to meet a target
+    if let Some(auto_power) = &cli.auto_power {
+        let powers = algorithm::tune_power(&mut ww, auto_power)?;
+        info!(
+            "Tuned for a {:?} of {}, equivalent to --power {}",
+            auto_power.metric,
+            auto_power.target,
+            join_values(powers.into_iter())
+        );
     }
+
+    Ok(ww)
+}
+
+/// Check the weight window header matches the geometry of the mesh
+///
+/// MCNP expects `nwg=1` with 10 mesh header values for rectangular meshes, and
+/// `nwg=2` or `nwg=3` with 16 values (including axis and vec) for cylindrical
+/// and spherical meshes.
+fn check_header(mesh: &Mesh, ww: &WeightWindow) -> Result<()> {
+    let (nwg, nr) = match mesh.geometry {
+        Geometry::Rectangular => (1, 10),
+        Geometry::Cylindrical => (2, 16),
+        Geometry::Spherical => (3, 16),
+    };
+
+    if ww.nwg != nwg || ww.nr != nr {
+        return Err(anyhow!(
+            "{:?} mesh {} gave a weight window header with nwg={} and nr={}, expected nwg={nwg} and nr={nr}",
+            mesh.geometry,
+            mesh.id,
+            ww.nwg,
+            ww.nr
+        ));
+    }
+    Ok(())
+}
+
+fn join_values(values: impl Iterator<Item = f64>) -> String {
+    values
+        .map(|v| f!("{v:.4}"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Write a weight window to a VTK file named by particle type
+pub fn generate_vtk(weight_window: &WeightWindow, cli: &VtkConfig) -> Result<()> {
+    // Write to disk, using the paticle type as a simple file name
+    let name = f!("ww_{:?}", Particle::from_id(weight_window.particle)).to_lowercase();
+    write_named_vtk(weight_window, cli, &name)
+}
+
+/// Write a VTK file flagging filled voxels with 1.0, and all others with 0.0
+pub fn generate_fill_vtk(
+    weight_window: &WeightWindow,
+    filled: &[bool],
+    cli: &VtkConfig,
+) -> Result<()> {
+    let mut flags = weight_window.clone();
+    flags.weights = filled.iter().map(|f| if *f { 1.0 } else { 0.0 }).collect();
+
+    let name = f!("ww_{:?}_filled", Particle::from_id(weight_window.particle)).to_lowercase();
+    write_named_vtk(&flags, cli, &name)
 }
 
-fn generate_vtk(weight_window: &WeightWindow, cli: &VtkConfig) -> Result<()> {
+/// Write a VTK file of the ratio between two weight windows
+pub fn generate_ratio_vtk(ratio: &WeightWindow, cli: &VtkConfig) -> Result<()> {
+    let name = f!("ww_{:?}_ratio", Particle::from_id(ratio.particle)).to_lowercase();
+    write_named_vtk(ratio, cli, &name)
+}
+
+fn write_named_vtk(weight_window: &WeightWindow, cli: &VtkConfig, name: &str) -> Result<()> {
     // Set up the conversion
     let convertor = build_converter(cli);
     let vtk = convertor.convert(weight_window);
@@ -106,32 +425,15 @@ fn generate_vtk(weight_window: &WeightWindow, cli: &VtkConfig) -> Result<()> {
         CliVtkFormat::Xml => match weight_window.nwg {
             // Mesh type 1=rec, 2=cyl, 3=sph
             1 => "vtr",
-            2 => "vtu",
+            2 | 3 => "vtu",
             _ => "vtk",
         },
         _ => "vtk",
     };
 
-    debug!(
-        "Ouput file: \"{}\"",
-        f!(
-            "ww_{:?}.{extension}",
-            Particle::from_id(weight_window.particle)
-        )
-        .to_lowercase()
-    );
-
-    // Write to disk, using the paticle type as a simple file name
-    write_vtk(
-        vtk,
-        f!(
-            "ww_{:?}.{extension}",
-            Particle::from_id(weight_window.particle)
-        )
-        .to_lowercase(),
-        cli.format.into(),
-    )
-    .map_err(|e| anyhow!(e))
+    let path = f!("{name}.{extension}");
+    debug!("Ouput file: \"{path}\"");
+    write_vtk(vtk, path, cli.format.into()).map_err(|e| anyhow!(e))
 }
 
 fn build_converter(cli: &VtkConfig) -> WeightsToVtk {
@@ -149,3 +451,84 @@ fn build_converter(cli: &VtkConfig) -> WeightsToVtk {
         })
         .build()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read mesh 14 from one of the meshtal fixtures
+    fn fixture(name: &str) -> Mesh {
+        let cli = WWConfig {
+            meshtal: f!("{}/data/meshtal/{name}", env!("CARGO_MANIFEST_DIR")),
+            number: 14,
+            ..Default::default()
+        };
+        try_meshtal_read(&cli).unwrap()
+    }
+
+    /// Check two fixtures give the same mesh and weights
+    fn assert_equivalent(a: &str, b: &str) {
+        let (a, b) = (fixture(a), fixture(b));
+        assert_eq!(a.imesh, b.imesh);
+        assert_eq!(a.jmesh, b.jmesh);
+        assert_eq!(a.kmesh, b.kmesh);
+        assert_eq!(a.emesh, b.emesh);
+
+        let values = |mesh: &Mesh| -> Vec<(f64, f64)> {
+            mesh.voxels.iter().map(|v| (v.result, v.error)).collect()
+        };
+        assert_eq!(values(&a), values(&b));
+
+        let cli = WWConfig::default();
+        let (ww_a, ww_b) = (
+            generate_weight_window(&a, &cli).unwrap(),
+            generate_weight_window(&b, &cli).unwrap(),
+        );
+        assert!(ww_a.weights.iter().any(|w| *w > 0.0));
+        assert_eq!(ww_a.weights, ww_b.weights);
+    }
+
+    #[test]
+    fn colsci_matches_col() {
+        assert_equivalent("col.msht", "colsci.msht");
+    }
+
+    #[test]
+    fn cfsci_matches_cf() {
+        assert_equivalent("cf.msht", "cfsci.msht");
+    }
+
+    fn header(nwg: u8, nr: u8) -> WeightWindow {
+        WeightWindow {
+            nwg,
+            nr,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn check_header_geometry() {
+        for (geometry, nwg, nr) in [
+            (Geometry::Rectangular, 1, 10),
+            (Geometry::Cylindrical, 2, 16),
+            (Geometry::Spherical, 3, 16),
+        ] {
+            let mesh = Mesh {
+                geometry,
+                ..Default::default()
+            };
+            assert!(check_header(&mesh, &header(nwg, nr)).is_ok());
+            assert!(check_header(&mesh, &header(nwg, 10 + 16 - nr)).is_err());
+        }
+    }
+
+    #[test]
+    fn check_header_spherical_mismatch() {
+        let mesh = Mesh {
+            geometry: Geometry::Spherical,
+            ..Default::default()
+        };
+        assert!(check_header(&mesh, &header(1, 10)).is_err());
+        assert!(check_header(&mesh, &header(2, 16)).is_err());
+    }
+}
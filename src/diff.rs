@@ -0,0 +1,165 @@
+//! Comparison of weight windows between iterations
+//!
+//! Weights are compared voxel by voxel as the log-ratio of the new weight to
+//! the old, so a value of 1.0 means the weight moved by an order of magnitude.
+
+use crate::geometry;
+use crate::groups::{self, Group};
+
+use ntools::mesh::Particle;
+use ntools::weights::WeightWindow;
+
+use anyhow::{anyhow, Result};
+use log::{error, warn};
+use std::fmt;
+
+/// Change in the weights of one energy/time group
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupDiff {
+    /// Particle type id
+    pub particle: u8,
+    /// Energy/time bounds of the group
+    pub group: Group,
+    /// Number of voxels non-analogue in both
+    pub compared: usize,
+    /// Mean log10(new/old) of the compared voxels
+    pub mean_log_ratio: f64,
+    /// Largest |log10(new/old)| of the compared voxels
+    pub max_log_ratio: f64,
+    /// Voxels that were non-analogue and are now analogue
+    pub to_analogue: usize,
+    /// Voxels that were analogue and are now non-analogue
+    pub to_non_analogue: usize,
+}
+
+impl fmt::Display for GroupDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: mean log-ratio {:+.3}, max {:.3} over {} voxels, {} became analogue, {} non-analogue",
+            self.group,
+            self.mean_log_ratio,
+            self.max_log_ratio,
+            self.compared,
+            self.to_analogue,
+            self.to_non_analogue
+        )
+    }
+}
+
+/// Compare every particle type found in both the old and new weight windows
+///
+/// Each particle must have the same geometry and energy/time groups in both,
+/// otherwise every difference is logged and an error returned. Particles in
+/// only one of them are skipped with a warning.
+pub fn diff_weight_windows(old: &[WeightWindow], new: &[WeightWindow]) -> Result<Vec<GroupDiff>> {
+    let pairs = matching_particles(old, new);
+
+    let mut compatible = true;
+    for (a, b) in &pairs {
+        let differences = geometry::grid_differences(a, b);
+        if !differences.is_empty() {
+            compatible = false;
+            error!(
+                "{:?} weights do not share the same grid:",
+                Particle::from_id(a.particle)
+            );
+            for d in differences {
+                error!(" - {d}");
+            }
+        }
+    }
+
+    if !compatible {
+        return Err(anyhow!("Unable to compare weights on different grids"));
+    }
+
+    Ok(pairs
+        .into_iter()
+        .flat_map(|(a, b)| diff_groups(a, b))
+        .collect())
+}
+
+/// Ratio of the new weights to the old, for plotting
+///
+/// Voxels that are analogue in either are set to zero.
+pub fn ratio_weight_window(old: &WeightWindow, new: &WeightWindow) -> WeightWindow {
+    let mut ratio = new.clone();
+    ratio.weights = old
+        .weights
+        .iter()
+        .zip(&new.weights)
+        .map(|(a, b)| if *a > 0.0 && *b > 0.0 { b / a } else { 0.0 })
+        .collect();
+    ratio
+}
+
+/// Pair up weight windows by particle type, warning about any left over
+fn matching_particles<'a>(
+    old: &'a [WeightWindow],
+    new: &'a [WeightWindow],
+) -> Vec<(&'a WeightWindow, &'a WeightWindow)> {
+    for ww in new {
+        if !old.iter().any(|a| a.particle == ww.particle) {
+            warn!(
+                "Warning: {:?} only in the new weights, skipping",
+                Particle::from_id(ww.particle)
+            );
+        }
+    }
+
+    old.iter()
+        .filter_map(|a| match new.iter().find(|b| b.particle == a.particle) {
+            Some(b) => Some((a, b)),
+            None => {
+                warn!(
+                    "Warning: {:?} only in the old weights, skipping",
+                    Particle::from_id(a.particle)
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+fn diff_groups(old: &WeightWindow, new: &WeightWindow) -> Vec<GroupDiff> {
+    let groups = groups::ww_groups(old);
+    let n_voxels = old.nfx * old.nfy * old.nfz;
+
+    groups
+        .into_iter()
+        .enumerate()
+        .map(|(g, group)| {
+            let range = g * n_voxels..(g + 1) * n_voxels;
+            let mut diff = GroupDiff {
+                particle: old.particle,
+                group,
+                compared: 0,
+                mean_log_ratio: 0.0,
+                max_log_ratio: 0.0,
+                to_analogue: 0,
+                to_non_analogue: 0,
+            };
+
+            let mut total = 0.0;
+            for (a, b) in old.weights[range.clone()].iter().zip(&new.weights[range]) {
+                match (*a > 0.0, *b > 0.0) {
+                    (true, true) => {
+                        let log_ratio = (b / a).log10();
+                        total += log_ratio;
+                        diff.max_log_ratio = diff.max_log_ratio.max(log_ratio.abs());
+                        diff.compared += 1;
+                    }
+                    (true, false) => diff.to_analogue += 1,
+                    (false, true) => diff.to_non_analogue += 1,
+                    (false, false) => (),
+                }
+            }
+
+            if diff.compared > 0 {
+                diff.mean_log_ratio = total / diff.compared as f64;
+            }
+            diff
+        })
+        .collect()
+}
@@ -0,0 +1,142 @@
+//! Handling of multiple weight window sets for the same particle type
+
+use crate::geometry;
+
+use ntools::mesh::Particle;
+use ntools::weights::WeightWindow;
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Strategy for combining sets with the same particle type
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    clap::ValueEnum,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateStrategy {
+    /// Refuse to continue
+    #[default]
+    Error,
+    /// Keep the first set given
+    First,
+    /// Keep the last set given
+    Last,
+    /// Geometric mean of the non-zero weights of every set
+    GeometricMean,
+    /// Minimum non-zero weight of every set
+    MinWeight,
+}
+
+/// Reduce the weight windows to one per particle type
+///
+/// The order of first appearance is preserved. Combining weights voxel by
+/// voxel requires every set for a particle to share the same mesh geometry
+/// and energy/time groups. Analogue (zero) weights are only kept where every
+/// set is analogue.
+pub fn resolve_duplicates(
+    weight_windows: Vec<WeightWindow>,
+    strategy: DuplicateStrategy,
+) -> Result<Vec<WeightWindow>> {
+    // group up by particle type, in order of first appearance
+    let mut groups: Vec<Vec<WeightWindow>> = Vec::new();
+    for ww in weight_windows {
+        match groups.iter_mut().find(|g| g[0].particle == ww.particle) {
+            Some(group) => group.push(ww),
+            None => groups.push(vec![ww]),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|group| combine_group(group, strategy))
+        .collect()
+}
+
+fn combine_group(
+    mut group: Vec<WeightWindow>,
+    strategy: DuplicateStrategy,
+) -> Result<WeightWindow> {
+    let particle = Particle::from_id(group[0].particle);
+    let n_sets = group.len();
+    if n_sets == 1 {
+        return Ok(group.remove(0));
+    }
+
+    match strategy {
+        DuplicateStrategy::Error => {
+            return Err(anyhow!(
+                "{n_sets} sets for {particle:?}, use --duplicates to choose how to combine them"
+            ))
+        }
+        DuplicateStrategy::First => {
+            warn!("Warning: Keeping the first of {n_sets} {particle:?} sets");
+            return Ok(group.remove(0));
+        }
+        DuplicateStrategy::Last => {
+            warn!("Warning: Keeping the last of {n_sets} {particle:?} sets");
+            return Ok(group.remove(n_sets - 1));
+        }
+        _ => (),
+    }
+
+    check_same_grid(&group)?;
+    info!("Combining {n_sets} {particle:?} sets using {strategy:?}");
+
+    let mut combined = group.remove(0);
+    for (idx, weight) in combined.weights.iter_mut().enumerate() {
+        let values = std::iter::once(*weight)
+            .chain(group.iter().map(|ww| ww.weights[idx]))
+            .filter(|w| *w > 0.0);
+
+        *weight = match strategy {
+            DuplicateStrategy::GeometricMean => {
+                let (sum, n) = values.fold((0.0, 0), |(sum, n), w| (sum + w.ln(), n + 1));
+                if n > 0 {
+                    (sum / n as f64).exp()
+                } else {
+                    0.0
+                }
+            }
+            _ => values.reduce(f64::min).unwrap_or(0.0),
+        };
+    }
+
+    Ok(combined)
+}
+
+/// Check every set shares the same geometry and energy/time groups
+fn check_same_grid(group: &[WeightWindow]) -> Result<()> {
+    let reference = &group[0];
+    let mut compatible = true;
+
+    for ww in &group[1..] {
+        let differences = geometry::grid_differences(reference, ww);
+        if !differences.is_empty() {
+            compatible = false;
+            error!(
+                "{:?} sets do not share the same grid:",
+                Particle::from_id(reference.particle)
+            );
+            for d in differences {
+                error!(" - {d}");
+            }
+        }
+    }
+
+    if compatible {
+        Ok(())
+    } else {
+        Err(anyhow!("Unable to combine sets on different grids"))
+    }
+}
@@ -0,0 +1,197 @@
+//! Geometry compatibility checks for multi-particle weight windows
+
+use crate::tolerance::all_close;
+
+use ntools::mesh::Particle;
+use ntools::utils::f;
+use ntools::weights::WeightWindow;
+
+use anyhow::{anyhow, Result};
+use log::error;
+
+/// Check that every weight window shares the same mesh geometry
+///
+/// All weight windows in a single WWINP file must have the same geometry
+/// type, origin, axis/vec, and i/j/k bounds. Every mismatch against the first
+/// weight window is logged as a per-axis report before returning an error.
+pub fn check_compatibility(weight_windows: &[WeightWindow]) -> Result<()> {
+    let Some(reference) = weight_windows.first() else {
+        return Ok(());
+    };
+
+    let mut compatible = true;
+    for ww in &weight_windows[1..] {
+        let differences = geometry_differences(reference, ww);
+        if !differences.is_empty() {
+            compatible = false;
+            error!(
+                "{:?} and {:?} meshes do not match:",
+                Particle::from_id(reference.particle),
+                Particle::from_id(ww.particle)
+            );
+            for d in differences {
+                error!(" - {d}");
+            }
+        }
+    }
+
+    if compatible {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Incompatible weight window geometries, refusing to write"
+        ))
+    }
+}
+
+/// List every difference in geometry between two weight windows
+pub fn geometry_differences(a: &WeightWindow, b: &WeightWindow) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    if a.nwg != b.nwg {
+        differences.push(f!(
+            "geometry: {} vs {}",
+            geometry_name(a.nwg),
+            geometry_name(b.nwg)
+        ));
+        // nothing else is meaningful to compare
+        return differences;
+    }
+
+    if !all_close(&a.origin, &b.origin) {
+        differences.push(f!("origin: {:?} vs {:?}", a.origin, b.origin));
+    }
+
+    // axis and vec only exist for cylindrical and spherical meshes
+    if a.nwg != 1 {
+        if !all_close(&a.director_1, &b.director_1) {
+            differences.push(f!("axis: {:?} vs {:?}", a.director_1, b.director_1));
+        }
+        if !all_close(&a.director_2, &b.director_2) {
+            differences.push(f!("vec: {:?} vs {:?}", a.director_2, b.director_2));
+        }
+    }
+
+    let axes = [
+        ("i", (a.nfx, a.ncx, &a.qps_x), (b.nfx, b.ncx, &b.qps_x)),
+        ("j", (a.nfy, a.ncy, &a.qps_y), (b.nfy, b.ncy, &b.qps_y)),
+        ("k", (a.nfz, a.ncz, &a.qps_z), (b.nfz, b.ncz, &b.qps_z)),
+    ];
+
+    for (axis, (a_nf, a_nc, a_qps), (b_nf, b_nc, b_qps)) in axes {
+        if a_nf != b_nf {
+            differences.push(f!("{axis} fine meshes: {a_nf} vs {b_nf}"));
+        }
+        if a_nc != b_nc {
+            differences.push(f!("{axis} coarse meshes: {a_nc} vs {b_nc}"));
+            continue;
+        }
+
+        // coarse meshes are (fine meshes, upper bound, ratio)
+        let column = |qps: &[[f64; 3]], n: usize| qps.iter().map(|q| q[n]).collect::<Vec<f64>>();
+        let (a_bounds, b_bounds) = (column(a_qps, 1), column(b_qps, 1));
+        if !all_close(&a_bounds, &b_bounds) {
+            differences.push(f!("{axis} bounds: {a_bounds:?} vs {b_bounds:?}"));
+        }
+        let (a_fine, b_fine) = (column(a_qps, 0), column(b_qps, 0));
+        if a_fine != b_fine {
+            differences.push(f!(
+                "{axis} fine meshes per coarse mesh: {a_fine:?} vs {b_fine:?}"
+            ));
+        }
+        let (a_ratio, b_ratio) = (column(a_qps, 2), column(b_qps, 2));
+        if !all_close(&a_ratio, &b_ratio) {
+            differences.push(f!("{axis} fine mesh ratios: {a_ratio:?} vs {b_ratio:?}"));
+        }
+    }
+
+    differences
+}
+
+/// List every difference in geometry and energy/time groups
+///
+/// Weights can only be compared voxel by voxel when everything matches.
+pub fn grid_differences(a: &WeightWindow, b: &WeightWindow) -> Vec<String> {
+    let mut differences = geometry_differences(a, b);
+    if a.e != b.e {
+        differences.push(f!("energy groups: {:?} vs {:?}", a.e, b.e));
+    }
+    if a.t != b.t {
+        differences.push(f!("time groups: {:?} vs {:?}", a.t, b.t));
+    }
+    if a.weights.len() != b.weights.len() {
+        differences.push(f!(
+            "number of weights: {} vs {}",
+            a.weights.len(),
+            b.weights.len()
+        ));
+    }
+    differences
+}
+
+fn geometry_name(nwg: u8) -> String {
+    match nwg {
+        1 => "rectangular".to_string(),
+        2 => "cylindrical".to_string(),
+        3 => "spherical".to_string(),
+        _ => f!("unknown (nwg={nwg})"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rectangular weight window with i coarse meshes of (fine, bound, ratio)
+    fn weight_window(qps_x: Vec<[f64; 3]>) -> WeightWindow {
+        WeightWindow {
+            nwg: 1,
+            nfx: qps_x.iter().map(|q| q[0] as usize).sum(),
+            ncx: qps_x.len(),
+            qps_x,
+            nfy: 1,
+            ncy: 1,
+            qps_y: vec![[1.0, 10.0, 1.0]],
+            nfz: 1,
+            ncz: 1,
+            qps_z: vec![[1.0, 10.0, 1.0]],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn geometry_differences_none() {
+        let a = weight_window(vec![[2.0, 5.0, 1.0], [1.0, 10.0, 1.0]]);
+        assert!(geometry_differences(&a, &a.clone()).is_empty());
+    }
+
+    #[test]
+    fn geometry_differences_bounds() {
+        let a = weight_window(vec![[1.0, 5.0, 1.0], [1.0, 10.0, 1.0]]);
+        let b = weight_window(vec![[1.0, 5.0, 1.0], [1.0, 20.0, 1.0]]);
+        assert_eq!(
+            geometry_differences(&a, &b),
+            vec!["i bounds: [5.0, 10.0] vs [5.0, 20.0]"]
+        );
+    }
+
+    #[test]
+    fn geometry_differences_fine_meshes() {
+        let a = weight_window(vec![[2.0, 5.0, 1.0], [1.0, 10.0, 1.0]]);
+        let b = weight_window(vec![[1.0, 5.0, 1.0], [2.0, 10.0, 1.0]]);
+        assert_eq!(
+            geometry_differences(&a, &b),
+            vec!["i fine meshes per coarse mesh: [2.0, 1.0] vs [1.0, 2.0]"]
+        );
+    }
+
+    #[test]
+    fn geometry_differences_coarse_meshes() {
+        let a = weight_window(vec![[2.0, 10.0, 1.0]]);
+        let b = weight_window(vec![[1.0, 5.0, 1.0], [1.0, 10.0, 1.0]]);
+        assert_eq!(
+            geometry_differences(&a, &b),
+            vec!["i coarse meshes: 1 vs 2"]
+        );
+    }
+}
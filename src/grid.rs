@@ -0,0 +1,225 @@
+//! Voxel indexing helpers for weight window meshes
+
+use ntools::mesh::Mesh;
+use ntools::weights::WeightWindow;
+
+use crate::tolerance::is_close;
+
+/// Unit steps to each of the six face-adjacent voxels
+pub const DIRECTIONS: [(isize, isize, isize); 6] = [
+    (-1, 0, 0),
+    (1, 0, 0),
+    (0, -1, 0),
+    (0, 1, 0),
+    (0, 0, -1),
+    (0, 0, 1),
+];
+
+/// Dimensions of a weight window mesh
+///
+/// Weights are stored by energy, time, then k, j, i with i changing fastest.
+/// Energy and time are combined into a single group index for convenience,
+/// i.e. `group = e * nt + t`.
+///
+/// Cylindrical meshes covering a full revolution in theta are periodic in k,
+/// so the first and last k voxels are adjacent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+    pub ne: usize,
+    pub nt: usize,
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    pub periodic_k: bool,
+}
+
+impl Grid {
+    /// Get the grid dimensions of a weight window
+    pub fn from_ww(ww: &WeightWindow) -> Self {
+        Self {
+            ne: ww.ne.max(1),
+            nt: ww.nt.max(1),
+            nx: ww.nfx,
+            ny: ww.nfy,
+            nz: ww.nfz,
+            periodic_k: ww.nwg == 2 && ww.qps_z.last().is_some_and(|q| is_close(q[1], 1.0)),
+        }
+    }
+
+    /// Number of unique energy/time groups
+    pub fn n_groups(&self) -> usize {
+        self.ne * self.nt
+    }
+
+    /// Number of voxels in a single energy/time group
+    pub fn n_voxels(&self) -> usize {
+        self.nx * self.ny * self.nz
+    }
+
+    /// Index into the weights vector
+    pub fn index(&self, group: usize, i: usize, j: usize, k: usize) -> usize {
+        ((group * self.nz + k) * self.ny + j) * self.nx + i
+    }
+
+    /// Index of the voxel offset by (di,dj,dk) in the same energy/time group
+    ///
+    /// Offsets in k wrap around periodic grids, but only while they are less
+    /// than half a revolution so that no voxel is reached from both sides.
+    pub fn shift(&self, idx: usize, di: isize, dj: isize, dk: isize) -> Option<usize> {
+        let (group, i, j, k) = self.ijk(idx);
+        let i = i.checked_add_signed(di).filter(|i| *i < self.nx)?;
+        let j = j.checked_add_signed(dj).filter(|j| *j < self.ny)?;
+        let k = if self.periodic_k && 2 * dk.unsigned_abs() < self.nz {
+            (k as isize + dk).rem_euclid(self.nz as isize) as usize
+        } else {
+            k.checked_add_signed(dk).filter(|k| *k < self.nz)?
+        };
+        Some(self.index(group, i, j, k))
+    }
+
+    /// Indices of the face-adjacent voxels in the same energy/time group
+    pub fn neighbours(&self, idx: usize) -> Vec<usize> {
+        DIRECTIONS
+            .iter()
+            .filter_map(|(di, dj, dk)| self.shift(idx, *di, *dj, *dk))
+            .collect()
+    }
+
+    /// Group and (i,j,k) indices for an index into the weights vector
+    pub fn ijk(&self, idx: usize) -> (usize, usize, usize, usize) {
+        let i = idx % self.nx;
+        let j = (idx / self.nx) % self.ny;
+        let k = (idx / (self.nx * self.ny)) % self.nz;
+        let group = idx / self.n_voxels();
+        (group, i, j, k)
+    }
+}
+
+/// Relative errors of the mesh voxels behind every weight
+pub fn weight_errors(mesh: &Mesh, ww: &WeightWindow, total: bool) -> Vec<f64> {
+    voxel_indices(mesh, ww, total)
+        .into_iter()
+        .map(|v| mesh.voxels.get(v).map(|voxel| voxel.error).unwrap_or(1.0))
+        .collect()
+}
+
+/// Index of the mesh voxel behind every weight
+///
+/// Mesh voxels are ordered by energy, time, then i, j, k with k changing
+/// fastest, so this maps them onto the weight ordering. Weight windows
+/// generated with `total` only use the last ('Total') energy/time group,
+/// otherwise every weight group is the tally group of the same index.
+pub fn voxel_indices(mesh: &Mesh, ww: &WeightWindow, total: bool) -> Vec<usize> {
+    let grid = Grid::from_ww(ww);
+    let (ebins, tbins) = (mesh.ebins(), mesh.tbins());
+
+    (0..grid.n_groups() * grid.n_voxels())
+        .map(|idx| {
+            let (group, i, j, k) = grid.ijk(idx);
+            let (e, t) = if total {
+                (ebins - 1, tbins - 1)
+            } else {
+                (group / grid.nt, group % grid.nt)
+            };
+            (((e * tbins + t) * mesh.iints + i) * mesh.jints + j) * mesh.kints + k
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2x1x1 mesh with two energy bins, i.e. three groups including 'Total'
+    fn two_group_mesh() -> Mesh {
+        Mesh {
+            imesh: vec![0.0, 1.0, 2.0],
+            iints: 2,
+            jmesh: vec![0.0, 1.0],
+            jints: 1,
+            kmesh: vec![0.0, 1.0],
+            kints: 1,
+            emesh: vec![0.0, 1.0, 20.0],
+            eints: 2,
+            tmesh: vec![],
+            tints: 1,
+            ..Default::default()
+        }
+    }
+
+    fn weight_window(ne: usize) -> WeightWindow {
+        WeightWindow {
+            ne,
+            nt: 1,
+            nfx: 2,
+            nfy: 1,
+            nfz: 1,
+            weights: vec![0.0; 2 * ne],
+            ..Default::default()
+        }
+    }
+
+    /// Single group 1x1x`nz` weight window with theta bounds up to `theta`
+    fn cylindrical(nz: usize, theta: f64) -> WeightWindow {
+        WeightWindow {
+            ne: 1,
+            nt: 1,
+            nfx: 1,
+            nfy: 1,
+            nfz: nz,
+            nwg: 2,
+            qps_z: vec![[nz as f64, theta, 1.0]],
+            weights: vec![0.0; nz],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn neighbours_rectangular() {
+        let grid = Grid::from_ww(&weight_window(1));
+        assert!(!grid.periodic_k);
+        assert_eq!(grid.neighbours(0), vec![1]);
+        assert_eq!(grid.shift(1, 1, 0, 0), None);
+    }
+
+    #[test]
+    fn neighbours_cylindrical_full_revolution() {
+        let grid = Grid::from_ww(&cylindrical(4, 1.0));
+        assert!(grid.periodic_k);
+        assert_eq!(grid.neighbours(0), vec![3, 1]);
+        assert_eq!(grid.neighbours(3), vec![2, 0]);
+        assert_eq!(grid.shift(0, 0, 0, -1), Some(3));
+        // half a revolution away is not wrapped, so never reached twice
+        assert_eq!(grid.shift(0, 0, 0, -2), None);
+        assert_eq!(grid.shift(0, 0, 0, 2), Some(2));
+    }
+
+    #[test]
+    fn neighbours_cylindrical_partial_revolution() {
+        let grid = Grid::from_ww(&cylindrical(4, 0.5));
+        assert!(!grid.periodic_k);
+        assert_eq!(grid.neighbours(0), vec![1]);
+        assert_eq!(grid.shift(0, 0, 0, -1), None);
+    }
+
+    #[test]
+    fn neighbours_cylindrical_two_voxels() {
+        let grid = Grid::from_ww(&cylindrical(2, 1.0));
+        assert_eq!(grid.neighbours(0), vec![1]);
+        assert_eq!(grid.neighbours(1), vec![0]);
+    }
+
+    #[test]
+    fn voxel_indices_per_group() {
+        let mesh = two_group_mesh();
+        let indices = voxel_indices(&mesh, &weight_window(2), false);
+        assert_eq!(indices, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn voxel_indices_total_group() {
+        let mesh = two_group_mesh();
+        let indices = voxel_indices(&mesh, &weight_window(1), true);
+        assert_eq!(indices, vec![4, 5]);
+    }
+}
@@ -0,0 +1,469 @@
+//! Per-group power/error values, given in order or matched by name
+//!
+//! Values are either a flat list in energy/time group order, or named specs
+//! such as `e<=1MeV:0.8 e>10MeV:0.6 default:0.7` that are matched against
+//! the real bounds of every group.
+
+use crate::tolerance::is_close;
+
+use ntools::mesh::Mesh;
+use ntools::utils::f;
+use ntools::weights::WeightWindow;
+
+use anyhow::{anyhow, Result};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Value for every energy/time group, or a named spec for some of them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GroupValue {
+    /// Plain value, one of a flat list in group order
+    Value(f64),
+    /// Named spec, e.g. `e<=1MeV:0.8` or `default:0.7`
+    Named(String),
+}
+
+impl From<f64> for GroupValue {
+    fn from(value: f64) -> Self {
+        GroupValue::Value(value)
+    }
+}
+
+impl FromStr for GroupValue {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.parse::<f64>() {
+            Ok(value) => Ok(GroupValue::Value(value)),
+            Err(_) => {
+                Spec::from_str(s)?;
+                Ok(GroupValue::Named(s.to_string()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for GroupValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GroupValue::Value(value) => write!(f, "{value}"),
+            GroupValue::Named(spec) => write!(f, "{spec}"),
+        }
+    }
+}
+
+/// Energy/time bounds of a weight window group
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    /// Lower and upper energy bounds (MeV), if any
+    pub energy: Option<(f64, f64)>,
+    /// Lower and upper time bounds (shakes), if any
+    pub time: Option<(f64, f64)>,
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let energy = self.energy.map_or("all energies".to_string(), |(lo, hi)| {
+            f!("e {lo:.3e} to {hi:.3e} MeV")
+        });
+        let time = self.time.map_or("all times".to_string(), |(lo, hi)| {
+            f!("t {lo:.3e} to {hi:.3e} shakes")
+        });
+        write!(f, "{energy}, {time}")
+    }
+}
+
+/// Every weight window group of a mesh, in energy then time order
+///
+/// Weight windows use the explicit tally groups, or a single group covering
+/// everything when only the 'Total' groups are used.
+pub fn weight_groups(mesh: &Mesh, total: bool) -> Vec<Group> {
+    let bins = |edges: &[f64]| -> Vec<Option<(f64, f64)>> {
+        match (edges.len() < 2, total) {
+            (true, _) => vec![None],
+            (false, true) => vec![Some((edges[0], edges[edges.len() - 1]))],
+            (false, false) => edges.windows(2).map(|b| Some((b[0], b[1]))).collect(),
+        }
+    };
+
+    let times = bins(&mesh.tmesh);
+    bins(&mesh.emesh)
+        .into_iter()
+        .flat_map(|energy| {
+            times.iter().map(move |time| Group {
+                energy,
+                time: *time,
+            })
+        })
+        .collect()
+}
+
+/// Every group of an existing weight window, in energy then time order
+///
+/// Only upper bounds are kept in a WWINP file, so the first energy group
+/// starts from zero and the first time group from negative infinity.
+pub fn ww_groups(ww: &WeightWindow) -> Vec<Group> {
+    let bins = |upper: &[f64], lowest: f64| -> Vec<Option<(f64, f64)>> {
+        if upper.len() < 2 {
+            return vec![None];
+        }
+        std::iter::once(lowest)
+            .chain(upper.iter().copied())
+            .collect::<Vec<f64>>()
+            .windows(2)
+            .map(|b| Some((b[0], b[1])))
+            .collect()
+    };
+
+    let times = bins(&ww.t, f64::NEG_INFINITY);
+    bins(&ww.e, 0.0)
+        .into_iter()
+        .flat_map(|energy| {
+            times.iter().map(move |time| Group {
+                energy,
+                time: *time,
+            })
+        })
+        .collect()
+}
+
+/// Resolve the values of an option into one value for every group
+///
+/// A single value applies to every group. Otherwise a flat list must have
+/// exactly one value per group, and named specs must match every group or
+/// include a default. Any mismatch is an error that lists the groups.
+pub fn resolve(values: &[GroupValue], groups: &[Group], option: &str) -> Result<Vec<f64>> {
+    let plain = values
+        .iter()
+        .filter_map(|v| match v {
+            GroupValue::Value(value) => Some(*value),
+            GroupValue::Named(_) => None,
+        })
+        .collect::<Vec<f64>>();
+
+    // flat list of values in group order
+    if plain.len() == values.len() {
+        return match plain.len() {
+            1 => Ok(vec![plain[0]; groups.len()]),
+            n if n == groups.len() => Ok(plain),
+            n if groups.len() == 1 => Err(list_groups(
+                groups,
+                f!("Expected a single {option} value for one group (e.g. --total), found {n}"),
+            )),
+            n => Err(list_groups(
+                groups,
+                f!("Expected 1 or {} {option} values, found {n}", groups.len()),
+            )),
+        };
+    } else if !plain.is_empty() {
+        return Err(anyhow!("Unable to mix plain and named {option} values"));
+    }
+
+    // named specs, where the first to match a group is used
+    let specs = values
+        .iter()
+        .map(|v| Spec::from_str(&v.to_string()))
+        .collect::<Result<Vec<Spec>>>()?;
+
+    let resolved = groups
+        .iter()
+        .map(|group| specs.iter().find(|s| s.matches(group)).map(|s| s.value))
+        .collect::<Vec<Option<f64>>>();
+
+    if resolved.iter().all(Option::is_some) {
+        Ok(resolved.into_iter().flatten().collect())
+    } else {
+        let unmatched = resolved
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_none())
+            .map(|(g, _)| g.to_string())
+            .collect::<Vec<String>>();
+        Err(list_groups(
+            groups,
+            f!(
+                "No {option} value for group(s) {}, add a default (e.g. default:0.7)",
+                unmatched.join(", ")
+            ),
+        ))
+    }
+}
+
+/// Log every available group and return the error
+fn list_groups(groups: &[Group], message: String) -> anyhow::Error {
+    error!("Available groups:");
+    for (g, group) in groups.iter().enumerate() {
+        error!(" - {g}: {group}");
+    }
+    anyhow!(message)
+}
+
+/// Named spec, i.e. conditions on the group bounds and a value
+#[derive(Debug, Clone, PartialEq)]
+struct Spec {
+    /// Every condition must hold, none for the default
+    conditions: Vec<Condition>,
+    value: f64,
+}
+
+impl Spec {
+    fn matches(&self, group: &Group) -> bool {
+        self.conditions.iter().all(|c| c.matches(group))
+    }
+}
+
+impl FromStr for Spec {
+    type Err = anyhow::Error;
+
+    /// Parse e.g. `e<=1MeV:0.8`, `e>10MeV,t<1e3:0.6`, or `default:0.7`
+    fn from_str(s: &str) -> Result<Self> {
+        let (selector, value) = s
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("Expected <selector>:<value>, found \"{s}\""))?;
+        let value = value
+            .parse::<f64>()
+            .map_err(|_| anyhow!("Invalid value in \"{s}\""))?;
+
+        let conditions = if selector.eq_ignore_ascii_case("default") {
+            Vec::new()
+        } else {
+            selector
+                .split(',')
+                .map(Condition::from_str)
+                .collect::<Result<Vec<Condition>>>()
+                .map_err(|e| anyhow!("{e} in \"{s}\""))?
+        };
+
+        Ok(Spec { conditions, value })
+    }
+}
+
+/// Condition on the energy or time bounds of a group
+///
+/// A group matches when it lies entirely below (`<`, `<=`) or above (`>`,
+/// `>=`) the bound.
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    energy: bool,
+    below: bool,
+    bound: f64,
+}
+
+impl Condition {
+    fn matches(&self, group: &Group) -> bool {
+        let bounds = if self.energy {
+            group.energy
+        } else {
+            group.time
+        };
+        let Some((lo, hi)) = bounds else {
+            return false;
+        };
+
+        if self.below {
+            hi <= self.bound || is_close(hi, self.bound)
+        } else {
+            lo >= self.bound || is_close(lo, self.bound)
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let energy = match s.chars().next() {
+            Some('e' | 'E') => true,
+            Some('t' | 'T') => false,
+            _ => return Err(anyhow!("Expected an 'e' or 't' condition")),
+        };
+
+        let rest = &s[1..];
+        let (below, number) = if let Some(n) = rest.strip_prefix("<=") {
+            (true, n)
+        } else if let Some(n) = rest.strip_prefix(">=") {
+            (false, n)
+        } else if let Some(n) = rest.strip_prefix('<') {
+            (true, n)
+        } else if let Some(n) = rest.strip_prefix('>') {
+            (false, n)
+        } else {
+            return Err(anyhow!("Expected <, <=, >, or >= after '{}'", &s[..1]));
+        };
+
+        Ok(Condition {
+            energy,
+            below,
+            bound: parse_bound(number, energy)?,
+        })
+    }
+}
+
+/// Parse a bound with an optional unit, converted to MeV or shakes
+fn parse_bound(s: &str, energy: bool) -> Result<f64> {
+    let units: &[(&str, f64)] = if energy {
+        &[("mev", 1.0), ("kev", 1e-3), ("ev", 1e-6)]
+    } else {
+        &[("shakes", 1.0), ("sh", 1.0), ("s", 1e8)]
+    };
+
+    let lower = s.trim().to_lowercase();
+    let (number, factor) = units
+        .iter()
+        .find_map(|(unit, factor)| lower.strip_suffix(unit).map(|n| (n, *factor)))
+        .unwrap_or((lower.as_str(), 1.0));
+
+    number
+        .trim()
+        .parse::<f64>()
+        .map(|n| n * factor)
+        .map_err(|_| anyhow!("Invalid bound \"{s}\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two energy groups split at 1 MeV, for all times
+    fn two_groups() -> Vec<Group> {
+        vec![
+            Group {
+                energy: Some((0.0, 1.0)),
+                time: None,
+            },
+            Group {
+                energy: Some((1.0, 20.0)),
+                time: None,
+            },
+        ]
+    }
+
+    fn named(specs: &[&str]) -> Vec<GroupValue> {
+        specs
+            .iter()
+            .map(|s| GroupValue::Named(s.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_bound_energy_units() {
+        assert_eq!(parse_bound("2", true).unwrap(), 2.0);
+        assert_eq!(parse_bound("1MeV", true).unwrap(), 1.0);
+        assert!(is_close(parse_bound("100keV", true).unwrap(), 0.1));
+        assert!(is_close(parse_bound("10 eV", true).unwrap(), 1e-5));
+        assert!(parse_bound("1 shakes", true).is_err());
+    }
+
+    #[test]
+    fn parse_bound_time_units() {
+        assert_eq!(parse_bound("5", false).unwrap(), 5.0);
+        assert_eq!(parse_bound("5shakes", false).unwrap(), 5.0);
+        assert_eq!(parse_bound("1e3sh", false).unwrap(), 1e3);
+        assert_eq!(parse_bound("1e-8s", false).unwrap(), 1.0);
+        assert!(parse_bound("1MeV", false).is_err());
+    }
+
+    #[test]
+    fn condition_operators() {
+        let below = Condition {
+            energy: true,
+            below: true,
+            bound: 1.0,
+        };
+        assert_eq!(Condition::from_str("e<=1MeV").unwrap(), below);
+        assert_eq!(Condition::from_str("E<1").unwrap(), below);
+
+        let above = Condition {
+            energy: false,
+            below: false,
+            bound: 1e8,
+        };
+        assert_eq!(Condition::from_str("t>=1s").unwrap(), above);
+        assert_eq!(Condition::from_str("t>1s").unwrap(), above);
+
+        assert!(Condition::from_str("e=1").is_err());
+        assert!(Condition::from_str("x<1").is_err());
+    }
+
+    #[test]
+    fn condition_matches_within_tolerance() {
+        let groups = two_groups();
+        let below = Condition::from_str("e<=0.9999999").unwrap();
+        assert!(below.matches(&groups[0]));
+        assert!(!below.matches(&groups[1]));
+
+        let above = Condition::from_str("e>1MeV").unwrap();
+        assert!(!above.matches(&groups[0]));
+        assert!(above.matches(&groups[1]));
+
+        // no time bounds to compare against
+        assert!(!Condition::from_str("t<1e3").unwrap().matches(&groups[0]));
+    }
+
+    #[test]
+    fn spec_default_matches_everything() {
+        let spec = Spec::from_str("default:0.7").unwrap();
+        assert!(spec.conditions.is_empty());
+        assert_eq!(spec.value, 0.7);
+        assert!(two_groups().iter().all(|g| spec.matches(g)));
+
+        assert!(Spec::from_str("default").is_err());
+        assert!(Spec::from_str("e<1:abc").is_err());
+    }
+
+    #[test]
+    fn resolve_named_with_default() {
+        let values = named(&["e<=1MeV:0.8", "default:0.7"]);
+        let resolved = resolve(&values, &two_groups(), "--power").unwrap();
+        assert_eq!(resolved, vec![0.8, 0.7]);
+    }
+
+    #[test]
+    fn resolve_named_first_match() {
+        let values = named(&["default:0.7", "e<=1MeV:0.8"]);
+        let resolved = resolve(&values, &two_groups(), "--power").unwrap();
+        assert_eq!(resolved, vec![0.7, 0.7]);
+    }
+
+    #[test]
+    fn resolve_named_unmatched() {
+        let values = named(&["e<=1MeV:0.8"]);
+        assert!(resolve(&values, &two_groups(), "--power").is_err());
+    }
+
+    #[test]
+    fn resolve_plain_values() {
+        let groups = two_groups();
+        assert_eq!(
+            resolve(&[0.5.into()], &groups, "--power").unwrap(),
+            vec![0.5, 0.5]
+        );
+        assert_eq!(
+            resolve(&[0.5.into(), 0.6.into()], &groups, "--power").unwrap(),
+            vec![0.5, 0.6]
+        );
+    }
+
+    #[test]
+    fn resolve_count_mismatch() {
+        let values = [0.5.into(), 0.6.into(), 0.7.into()];
+        let err = resolve(&values, &two_groups(), "--power").unwrap_err();
+        assert_eq!(err.to_string(), "Expected 1 or 2 --power values, found 3");
+
+        let err = resolve(&values, &two_groups()[..1], "--power").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Expected a single --power value"));
+    }
+
+    #[test]
+    fn resolve_mixed_values() {
+        let mut values = named(&["default:0.7"]);
+        values.push(0.5.into());
+        assert!(resolve(&values, &two_groups(), "--power").is_err());
+    }
+}
@@ -0,0 +1,51 @@
+//! Conversion of MCNP mesh tallies to mesh-based global weight windows
+//!
+//! The same pipeline used by the `mesh2ww` command line tool, without any
+//! dependence on process arguments. Every option is set explicitly through
+//! a [Recipe] of [WWConfig] sets and global options, built in code or read
+//! from a TOML file.
+//!
+//! ```no_run
+//! use mesh2ww::{Recipe, WWConfig};
+//!
+//! let recipe = Recipe {
+//!     sets: vec![WWConfig {
+//!         meshtal: "file.msht".to_string(),
+//!         number: 14,
+//!         power: vec![0.8.into()],
+//!         ..Default::default()
+//!     }],
+//!     ..Default::default()
+//! };
+//!
+//! // Generate the weight windows without writing anything
+//! let weight_windows = mesh2ww::collect_weight_windows(&recipe)?;
+//!
+//! // ...or generate and write the WWINP file in one go
+//! mesh2ww::convert(&recipe)?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod algorithm;
+pub mod combine;
+pub mod config;
+pub mod conversion;
+pub mod diff;
+pub mod duplicates;
+pub mod geometry;
+mod grid;
+pub mod groups;
+pub mod lint;
+pub mod postprocess;
+pub mod rebin;
+pub mod resample;
+mod tolerance;
+pub mod update;
+pub mod wrappers;
+pub mod wwinp;
+
+pub use config::{FileConfig, Recipe, ResampleConfig, VtkConfig, WWConfig};
+pub use conversion::{
+    collect_weight_windows, convert, diff_wwinp, generate_fill_vtk, generate_ratio_vtk,
+    generate_vtk, generate_weight_window, split_wwinp, try_meshtal_read, write_weight_windows,
+};
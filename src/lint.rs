@@ -0,0 +1,245 @@
+//! Validation of WWINP files before submitting jobs
+//!
+//! MCNP only reports a bad WWINP file after a long setup phase, and rarely
+//! says why. Every check here is reported as a [Finding], where any error
+//! means MCNP is likely to reject the file or produce nonsense.
+
+use crate::groups;
+use crate::wwinp::{self, Header};
+
+use ntools::mesh::Particle;
+use ntools::utils::f;
+use ntools::weights::WeightWindow;
+
+use anyhow::Result;
+use std::fmt;
+use std::path::Path;
+
+/// Options for validating a WWINP file
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintConfig {
+    /// Expect no unused particle entries in the header
+    pub trim: bool,
+    /// Largest ratio of the maximum to minimum non-zero weight in a group
+    pub max_range: f64,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            trim: false,
+            max_range: 1e10,
+        }
+    }
+}
+
+/// How serious a problem is
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    /// Unusual, but MCNP will run
+    Warning,
+    /// MCNP is likely to fail or misbehave
+    Error,
+}
+
+/// A single problem found in a WWINP file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Check a WWINP file for anything MCNP is likely to reject
+///
+/// Files that cannot be parsed at all are an error rather than a finding.
+/// Otherwise every problem with the header, mesh, groups, and weights is
+/// returned, and an empty list means the file passed.
+pub fn lint_wwinp(path: impl AsRef<Path>, config: &LintConfig) -> Result<Vec<Finding>> {
+    let header = wwinp::read_header(&path)?;
+    let (weight_windows, n_trailing) = wwinp::read_wwinp_trailing(&path)?;
+
+    let mut findings = lint_header(&header, config);
+    if n_trailing > 0 {
+        findings.push(Finding::error(f!(
+            "{n_trailing} unexpected values after the last weight"
+        )));
+    }
+    findings.extend(lint_mesh(&weight_windows[0], header.nr));
+    for ww in &weight_windows {
+        findings.extend(lint_groups(ww));
+        findings.extend(lint_weights(ww, config));
+    }
+
+    Ok(findings)
+}
+
+fn lint_header(header: &Header, config: &LintConfig) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if header.f != 1 {
+        findings.push(Finding::error(f!("File type if={}, expected 1", header.f)));
+    }
+    if header.iv != 1 && header.iv != 2 {
+        findings.push(Finding::error(f!(
+            "Time flag iv={}, expected 1 or 2",
+            header.iv
+        )));
+    } else if header.iv == 2 && header.nt.iter().all(|nt| *nt <= 1) {
+        findings.push(Finding::warning(
+            "Time-dependent header (iv=2) but no particle has time groups".to_string(),
+        ));
+    }
+
+    // unused particles have no energy groups, which MCNP expects unless trimmed
+    let padding = header.ne.iter().filter(|ne| **ne == 0).count();
+    if config.trim && padding > 0 {
+        findings.push(Finding::error(f!(
+            "Header is padded with {padding} unused particle entries, expected none with --trim"
+        )));
+    }
+
+    findings
+}
+
+fn lint_mesh(ww: &WeightWindow, nr: u8) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    match (ww.nwg, nr) {
+        (1, 10) | (2, 16) | (3, 16) => (),
+        (1, _) => findings.push(Finding::error(f!(
+            "Rectangular mesh (nwg=1) expects nr=10, found nr={nr}"
+        ))),
+        (2 | 3, _) => findings.push(Finding::error(f!(
+            "Cylindrical/spherical mesh (nwg={}) expects nr=16, found nr={nr}",
+            ww.nwg
+        ))),
+        (nwg, _) => findings.push(Finding::error(f!(
+            "Mesh type nwg={nwg}, expected 1, 2, or 3"
+        ))),
+    }
+
+    let axes = [
+        ("i", ww.nfx, &ww.qps_x),
+        ("j", ww.nfy, &ww.qps_y),
+        ("k", ww.nfz, &ww.qps_z),
+    ];
+
+    // coarse meshes are (fine meshes, upper bound, ratio)
+    for (axis, nf, qps) in axes {
+        let n_fine = qps.iter().map(|q| q[0]).sum::<f64>();
+        if qps.iter().any(|q| q[0] < 1.0 || q[0].fract() != 0.0) {
+            findings.push(Finding::error(f!(
+                "{axis} coarse meshes must each have a whole number of fine meshes"
+            )));
+        } else if n_fine as usize != nf {
+            findings.push(Finding::error(f!(
+                "{axis} header has {nf} fine meshes, coarse meshes have {n_fine}"
+            )));
+        }
+
+        let bounds = qps.iter().map(|q| q[1]).collect::<Vec<f64>>();
+        if !is_increasing(&bounds) {
+            findings.push(Finding::error(f!(
+                "{axis} coarse mesh bounds are not increasing: {bounds:?}"
+            )));
+        }
+    }
+
+    findings
+}
+
+fn lint_groups(ww: &WeightWindow) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let particle = Particle::from_id(ww.particle);
+
+    if !is_increasing(&ww.e) || ww.e.first().is_some_and(|e| *e <= 0.0) {
+        findings.push(Finding::error(f!(
+            "{particle:?} energy bounds are not positive and increasing: {:?}",
+            ww.e
+        )));
+    }
+    if !is_increasing(&ww.t) {
+        findings.push(Finding::error(f!(
+            "{particle:?} time bounds are not increasing: {:?}",
+            ww.t
+        )));
+    }
+
+    findings
+}
+
+fn lint_weights(ww: &WeightWindow, config: &LintConfig) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let particle = Particle::from_id(ww.particle);
+
+    let n_invalid = ww.weights.iter().filter(|w| !w.is_finite()).count();
+    if n_invalid > 0 {
+        findings.push(Finding::error(f!(
+            "{particle:?} has {n_invalid} weights that are not finite"
+        )));
+    }
+    let n_negative = ww.weights.iter().filter(|w| **w < 0.0).count();
+    if n_negative > 0 {
+        findings.push(Finding::error(f!(
+            "{particle:?} has {n_negative} negative weights"
+        )));
+    }
+
+    if ww.weights.iter().all(|w| *w == 0.0) {
+        findings.push(Finding::warning(f!(
+            "{particle:?} weights are all analogue (zero)"
+        )));
+        return findings;
+    }
+
+    // dynamic range of the non-zero weights in every group
+    let n_voxels = ww.nfx * ww.nfy * ww.nfz;
+    for (group, weights) in groups::ww_groups(ww)
+        .iter()
+        .zip(ww.weights.chunks(n_voxels.max(1)))
+    {
+        let (min, max) = weights
+            .iter()
+            .filter(|w| w.is_finite() && **w > 0.0)
+            .fold((f64::MAX, 0.0_f64), |(min, max), w| {
+                (min.min(*w), max.max(*w))
+            });
+
+        if max > 0.0 && max / min > config.max_range {
+            findings.push(Finding::error(f!(
+                "{particle:?} weights in group ({group}) span {:.2e}, above the limit of {:.2e}",
+                max / min,
+                config.max_range
+            )));
+        }
+    }
+
+    findings
+}
+
+/// Check values are finite and strictly increasing
+fn is_increasing(values: &[f64]) -> bool {
+    values.iter().all(|v| v.is_finite()) && values.windows(2).all(|w| w[0] < w[1])
+}
@@ -2,21 +2,17 @@
 #![doc(hidden)]
 
 mod cli;
-mod conversion;
 mod logging;
 mod parser;
-// mod update;
-mod wrappers;
 
 // internal modules
-use crate::cli::help_wanted;
-
-// neutronics toolbox
-use ntools::weights::write_multi_particle;
+use crate::cli::{help_wanted, subcommand};
 
 // other crates
 use anyhow::{anyhow, Result};
-use log::{debug, info};
+use log::{debug, error, info, warn};
+use mesh2ww::lint::Severity;
+use ntools::mesh::Particle;
 
 // Convenience types
 type ArgSet = Vec<String>;
@@ -30,24 +26,79 @@ fn main() -> Result<()> {
     // set up logging (Info is the default)
     logging::init_logging()?;
 
+    // subcommands work on existing WWINP files rather than mesh tallies
+    if let Some(name) = subcommand() {
+        return run_subcommand(&name);
+    }
+
     // split up the command line args by the '+' delimeter and parse each one
-    // through Clap to verify the arguments
+    // through Clap to verify the arguments, unless a recipe file is provided
     debug!("Parsing command line sets");
-    let ww_config_sets = parser::ww_config();
-    if ww_config_sets.is_empty() {
+    let recipe = parser::recipe()?;
+
+    // print the equivalent recipe rather than running if requested
+    if parser::dump_wanted() {
+        print!("{}", recipe.to_toml()?);
+        return Ok(());
+    }
+
+    if recipe.sets.is_empty() {
         return Err(anyhow!("No valid meshtal files were found"));
     }
 
-    // collect up all weight windows, just exclude any missing and warn the user
+    // collect up all weight windows and write the weight window file
     debug!("Generating weight windows");
-    let particle_weights = conversion::collect_weight_windows(ww_config_sets)?;
-
-    // Write the weight window file
-    let file_config = parser::file_config();
-    info!("Writing WWINP file");
-    debug!("Ouput file: \"{}\"", file_config.output);
-    write_multi_particle(&particle_weights, &file_config.output, !file_config.trim);
+    mesh2ww::convert(&recipe)?;
 
     info!("Conversion complete");
     Ok(())
 }
+
+fn run_subcommand(name: &str) -> Result<()> {
+    match name {
+        "split" => {
+            let job = parser::split_job();
+            info!("Splitting {}", job.wwinp);
+            mesh2ww::split_wwinp(&job.wwinp, &job.file, &job.vtk)?;
+            info!("Split complete");
+        }
+        "diff" => {
+            let job = parser::diff_job();
+            info!("Comparing {} to {}", job.new, job.old);
+            let diffs = mesh2ww::diff_wwinp(&job.old, &job.new, &job.vtk)?;
+
+            let mut particle = None;
+            for diff in diffs {
+                if particle != Some(diff.particle) {
+                    particle = Some(diff.particle);
+                    info!("{:?} weights:", Particle::from_id(diff.particle));
+                }
+                info!(" - {diff}");
+            }
+            info!("Comparison complete");
+        }
+        "check" => {
+            let job = parser::check_job();
+            info!("Checking {}", job.wwinp);
+            let findings = mesh2ww::lint::lint_wwinp(&job.wwinp, &job.lint)?;
+
+            let mut n_errors = 0;
+            for finding in findings {
+                match finding.severity {
+                    Severity::Error => {
+                        n_errors += 1;
+                        error!("{finding}");
+                    }
+                    Severity::Warning => warn!("Warning: {finding}"),
+                }
+            }
+
+            if n_errors > 0 {
+                return Err(anyhow!("{n_errors} problem(s) found in {}", job.wwinp));
+            }
+            info!("Check passed");
+        }
+        _ => return Err(anyhow!("Unknown subcommand \"{name}\"")),
+    }
+    Ok(())
+}
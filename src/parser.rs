@@ -1,56 +1,73 @@
 //! Handles parsing the command line arguments into something useful
 
-use crate::cli::{cli_init, is_flag_present};
-use crate::wrappers::{CliByteOrder, CliCompressor, CliVtkFormat};
+use crate::cli::{cli_init, command_init, is_flag_present};
 use crate::ArgSet;
 
+use mesh2ww::algorithm::{self, AutoPower, ResponseSource};
+use mesh2ww::combine::is_glob;
+use mesh2ww::duplicates::DuplicateStrategy;
+use mesh2ww::groups::GroupValue;
+use mesh2ww::lint::LintConfig;
+use mesh2ww::postprocess::SmoothConfig;
+use mesh2ww::wrappers::{CliByteOrder, CliCompressor, CliVtkFormat};
+use mesh2ww::wwinp;
+use mesh2ww::{FileConfig, Recipe, ResampleConfig, VtkConfig, WWConfig};
+
 use anyhow::{anyhow, Result};
 use clap::ArgMatches;
-use log::warn;
+use log::{info, warn};
 use std::env;
 use std::path::Path;
 
-#[derive(Debug)]
-pub struct WWConfig {
-    pub meshtal: String,
-    pub number: u32,
-    pub power: Vec<f64>,
-    pub error: Vec<f64>,
-    pub total: bool,
-    pub scale: f64,
-}
+/// Full job description, from a recipe file if provided
+pub fn recipe() -> Result<Recipe> {
+    let matches = all_argument_matches();
 
-#[derive(Debug)]
-pub struct VtkConfig {
-    pub vtk: bool,
-    pub format: CliVtkFormat,
-    pub compressor: CliCompressor,
-    pub endian: CliByteOrder,
-    pub resolution: u8,
+    match matches
+        .iter()
+        .find_map(|m| m.get_one::<String>("config").cloned())
+    {
+        Some(path) => {
+            if matches.iter().any(|m| m.contains_id("meshtal")) {
+                warn!("Warning: Command line sets are ignored when using --config");
+            }
+            info!("Reading recipe from {path}");
+            Recipe::from_file(path)
+        }
+        None => Ok(Recipe {
+            duplicates: duplicate_strategy(),
+            file: file_config(),
+            vtk: vtk_config(),
+            resample: resample_config(),
+            sets: ww_config()?,
+        }),
+    }
 }
 
-#[derive(Debug)]
-pub struct FileConfig {
-    pub trim: bool,
-    pub output: String,
+/// Check if the recipe should be printed instead of running
+pub fn dump_wanted() -> bool {
+    is_flag_present(&["--dump-config"])
 }
 
-pub fn ww_config() -> Vec<WWConfig> {
-    split_argument_sets()
-        .iter()
-        .filter_map(|arg_set| {
-            let cli = ww_set(arg_set.to_owned());
-            if let Err(e) = &cli {
-                warn!("{:?}, skipping", e);
-            }
-            cli.ok()
-        })
-        .collect::<Vec<WWConfig>>()
+/// Weight window options for every set on the command line
+///
+/// Sets with a missing meshtal file are skipped with a warning, but any invalid
+/// option is an error rather than silently dropping the set.
+pub fn ww_config() -> Result<Vec<WWConfig>> {
+    let mut sets = Vec::new();
+    for arg_set in split_argument_sets() {
+        if let Some(config) = ww_set(arg_set)? {
+            sets.push(config);
+        }
+    }
+    Ok(sets)
 }
 
 pub fn vtk_config() -> VtkConfig {
-    let matches = all_argument_matches();
+    vtk_options(&all_argument_matches())
+}
 
+fn vtk_options(matches: &[ArgMatches]) -> VtkConfig {
     // fine to unwrap these matches because a default has been set
     VtkConfig {
         vtk: is_flag_present(&["--vtk"]),
@@ -73,9 +90,31 @@ pub fn vtk_config() -> VtkConfig {
     }
 }
 
+pub fn resample_config() -> ResampleConfig {
+    let mut matches = all_argument_matches();
+
+    ResampleConfig {
+        reference: matches
+            .iter()
+            .find_map(|m| m.get_one::<usize>("resample").cloned()),
+        imesh: bounds_vector(&mut matches, "imesh"),
+        jmesh: bounds_vector(&mut matches, "jmesh"),
+        kmesh: bounds_vector(&mut matches, "kmesh"),
+    }
+}
+
+pub fn duplicate_strategy() -> DuplicateStrategy {
+    all_argument_matches()
+        .iter()
+        .find_map(|m| m.get_one::<DuplicateStrategy>("duplicates").cloned())
+        .unwrap_or_default()
+}
+
 pub fn file_config() -> FileConfig {
-    let matches = all_argument_matches();
+    file_options(&all_argument_matches())
+}
 
+fn file_options(matches: &[ArgMatches]) -> FileConfig {
     // fine to unwrap these matches because a default has been set
     FileConfig {
         trim: is_flag_present(&["--trim"]),
@@ -83,9 +122,82 @@ pub fn file_config() -> FileConfig {
             .iter()
             .find_map(|m| m.get_one::<String>("output").cloned())
             .unwrap_or("wwinp".to_string()),
+        append: is_flag_present(&["--append"]),
+    }
+}
+
+/// Inputs for the `split` subcommand
+pub struct SplitJob {
+    /// Path to the multi-particle WWINP file
+    pub wwinp: String,
+    /// Output prefix and padding options
+    pub file: FileConfig,
+    /// VTK options for every particle
+    pub vtk: VtkConfig,
+}
+
+pub fn split_job() -> SplitJob {
+    let matches = [subcommand_matches("split")];
+
+    // fine to unwrap because <wwinp> is required
+    SplitJob {
+        wwinp: matches[0].get_one::<String>("wwinp").cloned().unwrap(),
+        file: file_options(&matches),
+        vtk: vtk_options(&matches),
+    }
+}
+
+/// Inputs for the `diff` subcommand
+pub struct DiffJob {
+    /// Path to the old WWINP file
+    pub old: String,
+    /// Path to the new WWINP file
+    pub new: String,
+    /// VTK options for the ratio of every particle
+    pub vtk: VtkConfig,
+}
+
+pub fn diff_job() -> DiffJob {
+    let matches = [subcommand_matches("diff")];
+
+    // fine to unwrap because both paths are required
+    DiffJob {
+        old: matches[0].get_one::<String>("old").cloned().unwrap(),
+        new: matches[0].get_one::<String>("new").cloned().unwrap(),
+        vtk: vtk_options(&matches),
     }
 }
 
+/// Inputs for the `check` subcommand
+pub struct CheckJob {
+    /// Path to the WWINP file
+    pub wwinp: String,
+    /// Options for the checks
+    pub lint: LintConfig,
+}
+
+pub fn check_job() -> CheckJob {
+    let matches = subcommand_matches("check");
+    let default = LintConfig::default();
+
+    // fine to unwrap because <wwinp> is required
+    CheckJob {
+        wwinp: matches.get_one::<String>("wwinp").cloned().unwrap(),
+        lint: LintConfig {
+            trim: is_flag_present(&["--trim"]),
+            max_range: matches
+                .get_one::<f64>("max_range")
+                .cloned()
+                .unwrap_or(default.max_range),
+        },
+    }
+}
+
+/// Parse the arguments after the subcommand name
+fn subcommand_matches(name: &str) -> ArgMatches {
+    command_init(name).get_matches_from(env::args().skip(1))
+}
+
 fn split_argument_sets() -> Vec<ArgSet> {
     let name = env::args().next().unwrap();
     let raw_args = env::args().skip(1).collect::<Vec<String>>();
@@ -98,20 +210,28 @@ fn split_argument_sets() -> Vec<ArgSet> {
     tallies
 }
 
-fn powers_vector(matches: &mut ArgMatches) -> Vec<f64> {
+fn powers_vector(matches: &mut ArgMatches) -> Vec<GroupValue> {
     matches
-        .remove_many::<f64>("power")
+        .remove_many::<GroupValue>("power")
         .unwrap_or_default()
         .collect()
 }
 
-fn errors_vector(matches: &mut ArgMatches) -> Vec<f64> {
+fn errors_vector(matches: &mut ArgMatches) -> Vec<GroupValue> {
     matches
-        .remove_many::<f64>("error")
+        .remove_many::<GroupValue>("error")
         .unwrap_or_default()
         .collect()
 }
 
+fn bounds_vector(matches: &mut [ArgMatches], id: &str) -> Vec<f64> {
+    matches
+        .iter_mut()
+        .find_map(|m| m.remove_many::<f64>(id))
+        .map(|values| values.collect())
+        .unwrap_or_default()
+}
+
 fn all_argument_matches() -> Vec<ArgMatches> {
     split_argument_sets()
         .iter()
@@ -119,29 +239,186 @@ fn all_argument_matches() -> Vec<ArgMatches> {
         .collect()
 }
 
-fn ww_set(arguments: Vec<String>) -> Result<WWConfig> {
+fn ww_set(arguments: Vec<String>) -> Result<Option<WWConfig>> {
     let mut matches = cli_init().get_matches_from(arguments);
 
     let meshtal: Option<String> = matches.try_remove_one("meshtal")?;
     let number: Option<u32> = matches.try_remove_one("number")?;
 
+    let merge: Vec<String> = matches
+        .remove_many("merge")
+        .map(|paths| paths.collect())
+        .unwrap_or_default();
+
     match meshtal {
         Some(_) => {
-            // quickly check if all the files even exist
-            if !Path::new(&meshtal.clone().unwrap()).exists() {
-                return Err(anyhow!("Unable to find file \"{}\"", &meshtal.unwrap()));
+            // quickly check if all the files even exist, globs are checked later
+            for path in std::iter::once(meshtal.as_ref().unwrap()).chain(&merge) {
+                if !is_glob(path) && !Path::new(path).exists() {
+                    warn!("Warning: Unable to find file \"{path}\", skipping");
+                    return Ok(None);
+                }
             }
         }
         None => return Err(anyhow!("Empty <meshtal> positional argument in set")),
     }
 
+    // without a tally number, the input may be an existing WWINP file
+    let mut meshtal = meshtal.unwrap();
+    let wwinp = match number {
+        Some(_) => None,
+        None if wwinp::is_wwinp(&meshtal) => Some(std::mem::take(&mut meshtal)),
+        None => return Err(anyhow!("Missing <number> for meshtal \"{meshtal}\"")),
+    };
+
     // fine to unwrap these matches because a default has been set
-    Ok(WWConfig {
-        meshtal: meshtal.unwrap(),
-        number: number.unwrap(),
+    let config = WWConfig {
+        meshtal,
+        wwinp,
+        merge,
+        number: number.unwrap_or_default(),
         power: powers_vector(&mut matches),
+        auto_power: auto_power(&mut matches),
         error: errors_vector(&mut matches),
+        coverage: matches.remove_one("coverage"),
         total: matches.remove_one("total").unwrap(),
+        ww_energies: bounds_vector(std::slice::from_mut(&mut matches), "ww-energies"),
+        ww_times: bounds_vector(std::slice::from_mut(&mut matches), "ww-times"),
         scale: matches.remove_one("scale").unwrap(),
+        update: matches.remove_one("update"),
+        fill: matches.remove_one("fill"),
+        max_ratio: matches.remove_one("max-ratio"),
+        smooth: smooth_config(&mut matches),
+        method: matches.remove_one("method").unwrap(),
+        source: matches
+            .remove_many("source")
+            .map(|values| values.collect())
+            .unwrap_or_default(),
+        response: response_source(&mut matches)?,
+    };
+
+    // make sure the chosen algorithm accepts every option given
+    match config.wwinp {
+        Some(_) => wwinp::validate_wwinp_set(&config)?,
+        None => {
+            algorithm::validate(&config)?;
+        }
+    }
+    Ok(Some(config))
+}
+
+fn response_source(matches: &mut ArgMatches) -> Result<Option<ResponseSource>> {
+    let Some(values) = matches.remove_many::<String>("response") else {
+        return Ok(None);
+    };
+    let values = values.collect::<Vec<String>>();
+
+    // a single value that is not a number is taken to be a CSV file
+    if let [path] = values.as_slice() {
+        if path.parse::<f64>().is_err() {
+            if !Path::new(path).exists() {
+                return Err(anyhow!("Unable to find file \"{}\"", path));
+            }
+            return Ok(Some(ResponseSource::File(path.clone())));
+        }
+    }
+
+    let numbers = values
+        .iter()
+        .map(|v| v.parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|e| anyhow!("Invalid --response value: {e}"))?;
+
+    if numbers.len() % 2 != 0 {
+        return Err(anyhow!(
+            "Expected energy/value pairs for --response, found {} values",
+            numbers.len()
+        ));
+    }
+
+    Ok(Some(ResponseSource::Pairs(
+        numbers.chunks(2).map(|p| [p[0], p[1]]).collect(),
+    )))
+}
+
+fn auto_power(matches: &mut ArgMatches) -> Option<AutoPower> {
+    // fine to unwrap, the target is required with --auto-power
+    Some(AutoPower {
+        metric: matches.remove_one("auto-power")?,
+        target: matches.remove_one("target").unwrap(),
     })
 }
+
+fn smooth_config(matches: &mut ArgMatches) -> Option<SmoothConfig> {
+    // fine to unwrap these matches because a default has been set
+    Some(SmoothConfig {
+        filter: matches.remove_one("smooth")?,
+        kernel: matches.remove_one("kernel").unwrap(),
+        strength: matches.remove_one("strength").unwrap(),
+        analogue: matches.remove_one("analogue").unwrap(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Any file that exists but is not a WWINP file
+    const EXISTING: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+    fn arguments(args: &[&str]) -> Vec<String> {
+        std::iter::once("mesh2ww")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    fn response(values: &[&str]) -> Result<Option<ResponseSource>> {
+        let mut args = vec![EXISTING, "4", "--response"];
+        args.extend(values);
+        let mut matches = cli_init().get_matches_from(arguments(&args));
+        response_source(&mut matches)
+    }
+
+    #[test]
+    fn response_pairs() {
+        let source = response(&["1e-9", "3.2", "20", "8.1"]).unwrap();
+        assert_eq!(
+            source,
+            Some(ResponseSource::Pairs(vec![[1e-9, 3.2], [20.0, 8.1]]))
+        );
+    }
+
+    #[test]
+    fn response_count_mismatch() {
+        let err = response(&["1e-9", "3.2", "20"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected energy/value pairs for --response, found 3 values"
+        );
+    }
+
+    #[test]
+    fn response_invalid_value() {
+        assert!(response(&["1e-9", "abc"]).is_err());
+        assert!(response(&["/not/a/file.csv"]).is_err());
+    }
+
+    #[test]
+    fn missing_meshtal_skipped() {
+        let set = ww_set(arguments(&["/not/a/file.msht", "4"])).unwrap();
+        assert!(set.is_none());
+    }
+
+    #[test]
+    fn missing_number_is_error() {
+        let err = ww_set(arguments(&[EXISTING])).unwrap_err();
+        assert!(err.to_string().starts_with("Missing <number>"));
+    }
+
+    #[test]
+    fn invalid_option_is_error() {
+        let args = arguments(&[EXISTING, "4", "--method", "cadis", "--power", "0.5"]);
+        assert!(ww_set(args).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::grid::{Grid, DIRECTIONS};
+
+use ntools::weights::WeightWindow;
+
+use serde::{Deserialize, Serialize};
+
+/// Method for estimating weights in analogue holes
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum FillMethod {
+    /// Geometric mean of the non-zero neighbours (log-space interpolation)
+    Log,
+    /// Extrapolate the exponential trend of neighbours along each axis
+    Attenuation,
+}
+
+/// Estimate weights for analogue (zero) voxels from non-zero neighbours
+///
+/// Holes are filled from the outside in, one layer at a time, so every new
+/// estimate is based only on scored or previously filled voxels. Estimates are
+/// kept within the range of the original non-zero weights of each group to
+/// stop extrapolation running away across large holes.
+///
+/// Returns a flag for every voxel that was filled.
+pub fn fill_holes(ww: &mut WeightWindow, method: FillMethod) -> Vec<bool> {
+    let grid = Grid::from_ww(ww);
+    let mut filled = vec![false; ww.weights.len()];
+
+    // limits on the estimates for every energy/time group
+    let limits = (0..grid.n_groups())
+        .map(|group| {
+            let start = group * grid.n_voxels();
+            ww.weights[start..start + grid.n_voxels()]
+                .iter()
+                .filter(|w| **w > 0.0)
+                .fold((f64::MAX, 0.0_f64), |(lo, hi), w| (lo.min(*w), hi.max(*w)))
+        })
+        .collect::<Vec<(f64, f64)>>();
+
+    loop {
+        // estimate every hole on the current boundary before updating any
+        let front = (0..ww.weights.len())
+            .filter(|idx| ww.weights[*idx] <= 0.0)
+            .filter_map(|idx| {
+                let estimate = match method {
+                    FillMethod::Log => log_interpolate(&grid, &ww.weights, idx),
+                    FillMethod::Attenuation => attenuation_extrapolate(&grid, &ww.weights, idx)
+                        .or_else(|| log_interpolate(&grid, &ww.weights, idx)),
+                }?;
+                let (lo, hi) = limits[grid.ijk(idx).0];
+                Some((idx, estimate.clamp(lo, hi)))
+            })
+            .collect::<Vec<(usize, f64)>>();
+
+        if front.is_empty() {
+            break;
+        }
+
+        for (idx, estimate) in front {
+            ww.weights[idx] = estimate;
+            filled[idx] = true;
+        }
+    }
+
+    filled
+}
+
+/// Geometric mean of all non-zero face-adjacent weights
+fn log_interpolate(grid: &Grid, weights: &[f64], idx: usize) -> Option<f64> {
+    let logs = grid
+        .neighbours(idx)
+        .into_iter()
+        .map(|n| weights[n])
+        .filter(|w| *w > 0.0)
+        .map(f64::ln)
+        .collect::<Vec<f64>>();
+
+    if logs.is_empty() {
+        None
+    } else {
+        Some((logs.iter().sum::<f64>() / logs.len() as f64).exp())
+    }
+}
+
+/// Continue the exponential trend of the two nearest weights along each axis
+///
+/// For a direction with non-zero weights w1 and w2 at one and two voxels
+/// away, the estimate is w1^2 / w2. All available directions are averaged in
+/// log space.
+fn attenuation_extrapolate(grid: &Grid, weights: &[f64], idx: usize) -> Option<f64> {
+    let logs = DIRECTIONS
+        .iter()
+        .filter_map(|(di, dj, dk)| {
+            let w1 = weights[grid.shift(idx, *di, *dj, *dk)?];
+            let w2 = weights[grid.shift(idx, 2 * di, 2 * dj, 2 * dk)?];
+            (w1 > 0.0 && w2 > 0.0).then(|| 2.0 * w1.ln() - w2.ln())
+        })
+        .collect::<Vec<f64>>();
+
+    if logs.is_empty() {
+        None
+    } else {
+        Some((logs.iter().sum::<f64>() / logs.len() as f64).exp())
+    }
+}
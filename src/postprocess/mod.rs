@@ -0,0 +1,9 @@
+//! Post-processing of generated weight windows
+
+mod fill;
+mod ratio;
+mod smooth;
+
+pub use fill::{fill_holes, FillMethod};
+pub use ratio::limit_neighbour_ratio;
+pub use smooth::{smooth_weights, AnalogueMode, SmoothConfig, SmoothFilter};
@@ -0,0 +1,56 @@
+use crate::grid::Grid;
+
+use ntools::weights::WeightWindow;
+
+use log::warn;
+
+/// Safety net for the number of passes over the mesh
+const MAX_ITERATIONS: usize = 10_000;
+
+/// Cap the ratio between face-adjacent weights
+///
+/// Any weight more than `max_ratio` times smaller than a neighbour is raised
+/// to the neighbour weight divided by `max_ratio`. This is repeated until no
+/// more weights change, since raising one weight can affect its neighbours.
+///
+/// Weights are only ever raised, which reduces splitting rather than adding
+/// more. Analogue (zero) voxels are left alone. Returns the number of voxels
+/// that were changed.
+pub fn limit_neighbour_ratio(ww: &mut WeightWindow, max_ratio: f64) -> usize {
+    let grid = Grid::from_ww(ww);
+    let mut changed = vec![false; ww.weights.len()];
+
+    for iteration in 0.. {
+        if iteration == MAX_ITERATIONS {
+            warn!("Warning: Neighbour ratio limit did not converge");
+            break;
+        }
+
+        let mut updated = false;
+        for (idx, changed) in changed.iter_mut().enumerate() {
+            let weight = ww.weights[idx];
+            if weight <= 0.0 {
+                continue;
+            }
+
+            let lower_limit = grid
+                .neighbours(idx)
+                .into_iter()
+                .map(|n| ww.weights[n])
+                .fold(0.0, f64::max)
+                / max_ratio;
+
+            if weight < lower_limit {
+                ww.weights[idx] = lower_limit;
+                *changed = true;
+                updated = true;
+            }
+        }
+
+        if !updated {
+            break;
+        }
+    }
+
+    changed.iter().filter(|c| **c).count()
+}
@@ -0,0 +1,161 @@
+use crate::grid::Grid;
+
+use ntools::weights::WeightWindow;
+
+use serde::{Deserialize, Serialize};
+
+/// Smallest relative error used for error weighting, avoids dividing by zero
+const MIN_ERROR: f64 = 1e-3;
+
+/// Spatial filter applied to log10 of the weights
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum SmoothFilter {
+    /// Median of the kernel
+    Median,
+    /// Gaussian weighted average, sigma is half the kernel half-width
+    Gaussian,
+    /// Average weighted by the inverse variance of each voxel
+    Error,
+}
+
+/// Treatment of analogue (zero) voxels when smoothing
+#[derive(
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    clap::ValueEnum,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnalogueMode {
+    /// Analogue voxels stay analogue and are excluded from every kernel
+    #[default]
+    Keep,
+    /// Analogue voxels take the filtered value of any non-zero neighbours
+    Smooth,
+}
+
+/// Options for smoothing noisy weights
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmoothConfig {
+    /// Spatial filter to apply
+    pub filter: SmoothFilter,
+    /// Kernel half-width in voxels, e.g. 1 for a 3x3x3 kernel
+    pub kernel: usize,
+    /// Fraction of the filtered value to use, from 0.0 (none) to 1.0 (all)
+    pub strength: f64,
+    /// Treatment of analogue voxels
+    pub analogue: AnalogueMode,
+}
+
+impl Default for SmoothConfig {
+    fn default() -> Self {
+        Self {
+            filter: SmoothFilter::Median,
+            kernel: 1,
+            strength: 1.0,
+            analogue: AnalogueMode::Keep,
+        }
+    }
+}
+
+/// Smooth log10 of the weights of every energy/time group
+///
+/// Every voxel is replaced by the filtered value of all non-zero weights
+/// within a cube of the kernel half-width, blended with the original by the
+/// strength. Groups are never mixed. The relative errors are only used by the
+/// error-weighted filter, and must be in the same order as the weights.
+///
+/// Returns the number of voxels that were changed.
+pub fn smooth_weights(ww: &mut WeightWindow, errors: &[f64], config: &SmoothConfig) -> usize {
+    let grid = Grid::from_ww(ww);
+    let h = config.kernel as isize;
+    let sigma = (config.kernel as f64 / 2.0).max(0.5);
+    let strength = config.strength.clamp(0.0, 1.0);
+
+    let smoothed = (0..ww.weights.len())
+        .map(|idx| {
+            let weight = ww.weights[idx];
+            if weight <= 0.0 && config.analogue == AnalogueMode::Keep {
+                return weight;
+            }
+
+            // (log10 weight, squared distance, relative error) of the kernel
+            let mut kernel = Vec::with_capacity((2 * config.kernel + 1).pow(3));
+            for dk in -h..=h {
+                for dj in -h..=h {
+                    for di in -h..=h {
+                        let Some(n) = grid.shift(idx, di, dj, dk) else {
+                            continue;
+                        };
+                        if ww.weights[n] > 0.0 {
+                            kernel.push((
+                                ww.weights[n].log10(),
+                                (di * di + dj * dj + dk * dk) as f64,
+                                errors.get(n).copied().unwrap_or(1.0).max(MIN_ERROR),
+                            ));
+                        }
+                    }
+                }
+            }
+
+            let Some(filtered) = filter(&mut kernel, config.filter, sigma) else {
+                return weight;
+            };
+
+            if weight > 0.0 {
+                10_f64.powf((1.0 - strength) * weight.log10() + strength * filtered)
+            } else {
+                10_f64.powf(filtered)
+            }
+        })
+        .collect::<Vec<f64>>();
+
+    let n_changed = ww
+        .weights
+        .iter()
+        .zip(&smoothed)
+        .filter(|(a, b)| a != b)
+        .count();
+
+    ww.weights = smoothed;
+    n_changed
+}
+
+/// Apply the filter to the (log10 weight, squared distance, error) values
+fn filter(kernel: &mut [(f64, f64, f64)], method: SmoothFilter, sigma: f64) -> Option<f64> {
+    if kernel.is_empty() {
+        return None;
+    }
+
+    let weighted_mean = |weight: &dyn Fn(&(f64, f64, f64)) -> f64| {
+        let (sum, total) = kernel.iter().fold((0.0, 0.0), |(sum, total), v| {
+            (sum + weight(v) * v.0, total + weight(v))
+        });
+        sum / total
+    };
+
+    Some(match method {
+        SmoothFilter::Median => {
+            kernel.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mid = kernel.len() / 2;
+            if kernel.len() % 2 == 0 {
+                0.5 * (kernel[mid - 1].0 + kernel[mid].0)
+            } else {
+                kernel[mid].0
+            }
+        }
+        SmoothFilter::Gaussian => weighted_mean(&|v| (-v.1 / (2.0 * sigma * sigma)).exp()),
+        SmoothFilter::Error => weighted_mean(&|v| 1.0 / (v.2 * v.2)),
+    })
+}
@@ -0,0 +1,190 @@
+//! Rebinning of tally energy/time groups to a coarser weight window structure
+
+use crate::config::WWConfig;
+use crate::tolerance::is_close;
+
+use ntools::mesh::{Mesh, Voxel};
+
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+
+/// Collapse the tally groups of a mesh onto the weight window groups of a set
+///
+/// Boundaries are the upper edges of every weight window group, as in the
+/// WWGE/WWGT cards, and each must line up with a tally bin edge. The last
+/// boundary must be the upper edge of the tally. Meshes are returned as they
+/// are if no boundaries are given.
+pub fn rebin_mesh(mut mesh: Mesh, cli: &WWConfig) -> Result<Mesh> {
+    if cli.ww_energies.is_empty() && cli.ww_times.is_empty() {
+        return Ok(mesh);
+    }
+
+    let (e_map, emesh) = group_map(&mesh.emesh, mesh.ebins(), &cli.ww_energies, "energy")?;
+    let (t_map, tmesh) = group_map(&mesh.tmesh, mesh.tbins(), &cli.ww_times, "time")?;
+    info!(
+        "Rebinning mesh {} to {} energy and {} time groups",
+        mesh.id,
+        emesh.len().saturating_sub(1),
+        tmesh.len().saturating_sub(1)
+    );
+
+    let n_spatial = mesh.iints * mesh.jints * mesh.kints;
+    let n_ebins = n_bins(&e_map);
+    let n_tbins = n_bins(&t_map);
+    let n_old_tbins = t_map.len();
+
+    // running sums of (result, variance) for every new voxel
+    let mut sums = vec![(0.0, 0.0); n_ebins * n_tbins * n_spatial];
+    for (e, new_e) in e_map.iter().enumerate() {
+        for (t, new_t) in t_map.iter().enumerate() {
+            let (Some(new_e), Some(new_t)) = (new_e, new_t) else {
+                continue;
+            };
+            let old = (e * n_old_tbins + t) * n_spatial;
+            let new = (new_e * n_tbins + new_t) * n_spatial;
+            for s in 0..n_spatial {
+                let v = &mesh.voxels[old + s];
+                let result = v.result.max(0.0);
+                sums[new + s].0 += result;
+                sums[new + s].1 += (result * v.error).powi(2);
+            }
+        }
+    }
+
+    mesh.voxels = sums
+        .into_iter()
+        .enumerate()
+        .map(|(index, (result, variance))| Voxel {
+            index,
+            result,
+            error: if result > 0.0 {
+                variance.sqrt() / result
+            } else {
+                0.0
+            },
+        })
+        .collect();
+
+    if !cli.ww_energies.is_empty() {
+        mesh.eints = emesh.len() - 1;
+        mesh.emesh = emesh;
+    }
+    if !cli.ww_times.is_empty() {
+        mesh.tints = tmesh.len() - 1;
+        mesh.tmesh = tmesh;
+    }
+    Ok(mesh)
+}
+
+/// Number of new groups in the voxel data, including any 'Total' group
+fn n_bins(map: &[Option<usize>]) -> usize {
+    map.iter().flatten().max().map_or(1, |max| max + 1)
+}
+
+/// Map every tally group onto a new group, and get the new bin edges
+///
+/// Explicit tally groups map onto the group containing them. The 'Total'
+/// group maps onto the new 'Total' group, or is dropped if there is only one
+/// new group because the explicit groups already sum to the same thing.
+fn group_map(
+    edges: &[f64],
+    n_bins: usize,
+    boundaries: &[f64],
+    kind: &str,
+) -> Result<(Vec<Option<usize>>, Vec<f64>)> {
+    let n_groups = edges.len().saturating_sub(1);
+
+    // nothing requested, keep every group as it is
+    if boundaries.is_empty() {
+        return Ok(((0..n_bins).map(Some).collect(), edges.to_vec()));
+    }
+
+    if n_groups == 0 {
+        return Err(anyhow!("Unable to rebin a mesh without {kind} bins"));
+    }
+
+    let lower = edges[0];
+    let upper = edges[n_groups];
+
+    // find the tally edge index for every boundary
+    let mut indices = Vec::with_capacity(boundaries.len());
+    for b in boundaries {
+        let idx = edges.iter().position(|e| is_close(*e, *b)).ok_or_else(|| {
+            anyhow!("The {kind} boundary {b} is not a tally bin edge, expected one of {edges:?}")
+        })?;
+        if idx == 0 || indices.last().is_some_and(|last| idx <= *last) {
+            return Err(anyhow!(
+                "The {kind} boundaries must be increasing and above the lower tally edge {lower}"
+            ));
+        }
+        indices.push(idx);
+    }
+    if indices.last() != Some(&n_groups) {
+        return Err(anyhow!(
+            "The last {kind} boundary must be the upper tally edge {upper}"
+        ));
+    }
+
+    let mut map = Vec::with_capacity(n_bins);
+    for g in 0..n_groups {
+        map.push(Some(indices.iter().position(|idx| g < *idx).unwrap()));
+    }
+    if n_bins > n_groups {
+        map.push((indices.len() > 1).then_some(indices.len()));
+    }
+    debug!("Tally {kind} group map: {map:?}");
+
+    let new_edges = std::iter::once(lower)
+        .chain(indices.iter().map(|idx| edges[*idx]))
+        .collect();
+    Ok((map, new_edges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three tally groups plus the 'Total' group
+    const EDGES: [f64; 4] = [0.0, 1.0, 10.0, 20.0];
+
+    #[test]
+    fn group_map_unchanged() {
+        let (map, edges) = group_map(&EDGES, 4, &[], "energy").unwrap();
+        assert_eq!(map, vec![Some(0), Some(1), Some(2), Some(3)]);
+        assert_eq!(edges, EDGES.to_vec());
+    }
+
+    #[test]
+    fn group_map_collapse() {
+        let (map, edges) = group_map(&EDGES, 4, &[1.0, 20.0], "energy").unwrap();
+        assert_eq!(map, vec![Some(0), Some(1), Some(1), Some(2)]);
+        assert_eq!(edges, vec![0.0, 1.0, 20.0]);
+    }
+
+    #[test]
+    fn group_map_single_group_drops_total() {
+        let (map, edges) = group_map(&EDGES, 4, &[20.0], "energy").unwrap();
+        assert_eq!(map, vec![Some(0), Some(0), Some(0), None]);
+        assert_eq!(edges, vec![0.0, 20.0]);
+    }
+
+    #[test]
+    fn group_map_within_tolerance() {
+        let (_, edges) = group_map(&EDGES, 4, &[10.000001, 20.0], "energy").unwrap();
+        assert_eq!(edges, vec![0.0, 10.0, 20.0]);
+    }
+
+    #[test]
+    fn group_map_invalid_boundaries() {
+        // not a tally edge
+        assert!(group_map(&EDGES, 4, &[5.0, 20.0], "energy").is_err());
+        // not increasing
+        assert!(group_map(&EDGES, 4, &[10.0, 1.0, 20.0], "energy").is_err());
+        // the lower tally edge
+        assert!(group_map(&EDGES, 4, &[0.0, 20.0], "energy").is_err());
+        // missing the upper tally edge
+        assert!(group_map(&EDGES, 4, &[1.0, 10.0], "energy").is_err());
+        // no tally bins at all
+        assert!(group_map(&[], 1, &[1.0], "time").is_err());
+    }
+}
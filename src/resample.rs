@@ -0,0 +1,315 @@
+//! Resampling of mesh tallies onto a common weight window grid
+
+use crate::config::ResampleConfig;
+use crate::tolerance::all_close;
+
+use ntools::mesh::{Geometry, Mesh, Voxel};
+
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+
+/// Resample every mesh onto a common grid, if one is requested
+///
+/// The grid is either taken from a reference set or given explicitly as i/j/k
+/// bounds. Any mesh that does not already share the grid has its flux
+/// volume-averaged onto the new voxels, with errors propagated.
+pub fn resample_meshes(meshes: &mut [Mesh], config: &ResampleConfig) -> Result<()> {
+    let Some(bounds) = target_bounds(meshes, config)? else {
+        return Ok(());
+    };
+
+    for mesh in meshes.iter_mut() {
+        if has_bounds(mesh, &bounds) {
+            debug!("{:?} mesh {} already on the grid", mesh.particle, mesh.id);
+            continue;
+        }
+        info!(
+            "Resampling {:?} mesh {} onto the common grid",
+            mesh.particle, mesh.id
+        );
+        *mesh = resample(mesh, &bounds)?;
+    }
+
+    Ok(())
+}
+
+/// Volume-average a mesh onto new i/j/k bounds in the same frame
+///
+/// Every new voxel is the volume-weighted average of all overlapping voxels,
+/// and regions not covered by the original mesh are left as zero.
+pub fn resample(mesh: &Mesh, bounds: &[Vec<f64>; 3]) -> Result<Mesh> {
+    let measures = match mesh.geometry {
+        Geometry::Rectangular => [Measure::Linear; 3],
+        Geometry::Cylindrical => [Measure::Area, Measure::Linear, Measure::Linear],
+        Geometry::Spherical => [Measure::Volume, Measure::Polar, Measure::Linear],
+    };
+
+    // overlap measures are separable, so volumes are products across axes
+    let i_overlap = overlaps(&mesh.imesh, &bounds[0], measures[0]);
+    let j_overlap = overlaps(&mesh.jmesh, &bounds[1], measures[1]);
+    let k_overlap = overlaps(&mesh.kmesh, &bounds[2], measures[2]);
+
+    let (ni, nj, nk) = (i_overlap.len(), j_overlap.len(), k_overlap.len());
+    let n_source = mesh.iints * mesh.jints * mesh.kints;
+    let n_groups = mesh.voxels.len() / n_source.max(1);
+
+    let mut voxels = Vec::with_capacity(n_groups * ni * nj * nk);
+    for group in 0..n_groups {
+        let source = &mesh.voxels[group * n_source..(group + 1) * n_source];
+        for i_list in &i_overlap {
+            for j_list in &j_overlap {
+                for k_list in &k_overlap {
+                    let (mut sum, mut variance, mut volume) = (0.0, 0.0, 0.0);
+                    for (si, wi) in i_list {
+                        for (sj, wj) in j_list {
+                            for (sk, wk) in k_list {
+                                let w = wi * wj * wk;
+                                let v = &source[(si * mesh.jints + sj) * mesh.kints + sk];
+                                sum += w * v.result;
+                                variance += (w * v.result * v.error).powi(2);
+                                volume += w;
+                            }
+                        }
+                    }
+
+                    let (result, error) = if volume > 0.0 && sum > 0.0 {
+                        (sum / volume, variance.sqrt() / sum)
+                    } else {
+                        (0.0, 0.0)
+                    };
+
+                    voxels.push(Voxel {
+                        index: voxels.len(),
+                        result,
+                        error,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut resampled = mesh.clone();
+    resampled.imesh = bounds[0].clone();
+    resampled.jmesh = bounds[1].clone();
+    resampled.kmesh = bounds[2].clone();
+    resampled.iints = ni;
+    resampled.jints = nj;
+    resampled.kints = nk;
+    resampled.voxels = voxels;
+    Ok(resampled)
+}
+
+/// Find the common grid bounds from the reference set or explicit values
+fn target_bounds(meshes: &[Mesh], config: &ResampleConfig) -> Result<Option<[Vec<f64>; 3]>> {
+    if let Some(n) = config.reference {
+        let reference = meshes
+            .get(n.wrapping_sub(1))
+            .ok_or_else(|| anyhow!("No set {n} to use as the resampling reference"))?;
+
+        // resampling is only valid within the same coordinate frame
+        for mesh in meshes {
+            if mesh.geometry != reference.geometry
+                || !all_close(&mesh.origin, &reference.origin)
+                || !all_close(&mesh.axs, &reference.axs)
+                || !all_close(&mesh.vec, &reference.vec)
+            {
+                return Err(anyhow!(
+                    "Mesh {} geometry, origin, or orientation differs from reference mesh {}",
+                    mesh.id,
+                    reference.id
+                ));
+            }
+        }
+
+        info!("Using set {n} (mesh {}) as the common grid", reference.id);
+        return Ok(Some([
+            reference.imesh.clone(),
+            reference.jmesh.clone(),
+            reference.kmesh.clone(),
+        ]));
+    }
+
+    if config.imesh.is_empty() && config.jmesh.is_empty() && config.kmesh.is_empty() {
+        return Ok(None);
+    }
+
+    for (axis, bounds) in [
+        ("i", &config.imesh),
+        ("j", &config.jmesh),
+        ("k", &config.kmesh),
+    ] {
+        if bounds.len() < 2 || bounds.windows(2).any(|b| b[1] <= b[0]) {
+            return Err(anyhow!(
+                "Explicit {axis} bounds must be at least two increasing values"
+            ));
+        }
+    }
+
+    Ok(Some([
+        config.imesh.clone(),
+        config.jmesh.clone(),
+        config.kmesh.clone(),
+    ]))
+}
+
+fn has_bounds(mesh: &Mesh, bounds: &[Vec<f64>; 3]) -> bool {
+    all_close(&mesh.imesh, &bounds[0])
+        && all_close(&mesh.jmesh, &bounds[1])
+        && all_close(&mesh.kmesh, &bounds[2])
+}
+
+/// Measure along a single axis, proportional to the voxel volume it spans
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Measure {
+    /// Cartesian axes, and any axial or azimuthal angle
+    Linear,
+    /// Cylindrical radius, measured in r^2
+    Area,
+    /// Spherical radius, measured in r^3
+    Volume,
+    /// Spherical polar angle in revolutions, measured in 1 - cos(angle)
+    Polar,
+}
+
+impl Measure {
+    fn of(&self, x: f64) -> f64 {
+        match self {
+            Measure::Linear => x,
+            Measure::Area => x * x,
+            Measure::Volume => x * x * x,
+            Measure::Polar => 1.0 - (std::f64::consts::TAU * x).cos(),
+        }
+    }
+}
+
+/// Overlap of every source bin with each target bin along a single axis
+///
+/// Overlaps are in the measure of the axis, so that the products across axes
+/// are proportional to voxel volumes for any geometry.
+fn overlaps(source: &[f64], target: &[f64], measure: Measure) -> Vec<Vec<(usize, f64)>> {
+    target
+        .windows(2)
+        .map(|t| {
+            source
+                .windows(2)
+                .enumerate()
+                .filter_map(|(s, b)| {
+                    let overlap = measure.of(t[1].min(b[1])) - measure.of(t[0].max(b[0]));
+                    (overlap > 0.0).then_some((s, overlap))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Single group mesh from its bounds and (result, error) of every voxel
+    fn mesh(geometry: Geometry, bounds: [&[f64]; 3], values: &[(f64, f64)]) -> Mesh {
+        Mesh {
+            geometry,
+            imesh: bounds[0].to_vec(),
+            iints: bounds[0].len() - 1,
+            jmesh: bounds[1].to_vec(),
+            jints: bounds[1].len() - 1,
+            kmesh: bounds[2].to_vec(),
+            kints: bounds[2].len() - 1,
+            voxels: values
+                .iter()
+                .enumerate()
+                .map(|(index, (result, error))| Voxel {
+                    index,
+                    result: *result,
+                    error: *error,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= 1e-12 * b.abs(), "{a} != {b}");
+    }
+
+    /// Resample the i axis of a two bin mesh onto new i bounds
+    fn resample_i(geometry: Geometry, imesh: &[f64], target: &[f64]) -> Voxel {
+        let bounds = [imesh, &[0.0, 1.0], &[0.0, 1.0]];
+        let mesh = mesh(geometry, bounds, &[(3.0, 0.1), (6.0, 0.2)]);
+        let mut resampled =
+            resample(&mesh, &[target.to_vec(), vec![0.0, 1.0], vec![0.0, 1.0]]).unwrap();
+        assert_eq!(resampled.voxels.len(), 1);
+        resampled.voxels.remove(0)
+    }
+
+    #[test]
+    fn resample_rectangular_merge() {
+        // widths of 1 and 2
+        let voxel = resample_i(Geometry::Rectangular, &[0.0, 1.0, 3.0], &[0.0, 3.0]);
+        assert_close(voxel.result, (3.0 + 2.0 * 6.0) / 3.0);
+        assert_close(
+            voxel.error,
+            (0.3_f64.powi(2) + 2.4_f64.powi(2)).sqrt() / 15.0,
+        );
+    }
+
+    #[test]
+    fn resample_rectangular_partial() {
+        // overlaps of 0.5 and 1
+        let voxel = resample_i(Geometry::Rectangular, &[0.0, 1.0, 3.0], &[0.5, 2.0]);
+        assert_close(voxel.result, (0.5 * 3.0 + 6.0) / 1.5);
+        assert_close(
+            voxel.error,
+            (0.15_f64.powi(2) + 1.2_f64.powi(2)).sqrt() / 7.5,
+        );
+    }
+
+    #[test]
+    fn resample_cylindrical_merge() {
+        // rings weighted by r^2, i.e. 1 and 3
+        let voxel = resample_i(Geometry::Cylindrical, &[0.0, 1.0, 2.0], &[0.0, 2.0]);
+        assert_close(voxel.result, (3.0 + 3.0 * 6.0) / 4.0);
+        assert_close(
+            voxel.error,
+            (0.3_f64.powi(2) + 3.6_f64.powi(2)).sqrt() / 21.0,
+        );
+    }
+
+    #[test]
+    fn resample_cylindrical_partial() {
+        // rings weighted by r^2, i.e. 1 - 0.25 and 2.25 - 1
+        let voxel = resample_i(Geometry::Cylindrical, &[0.0, 1.0, 2.0], &[0.5, 1.5]);
+        let sum = 0.75 * 3.0 + 1.25 * 6.0;
+        assert_close(voxel.result, sum / 2.0);
+        assert_close(
+            voxel.error,
+            ((0.75 * 0.3_f64).powi(2) + (1.25 * 1.2_f64).powi(2)).sqrt() / sum,
+        );
+    }
+
+    #[test]
+    fn resample_spherical_radius() {
+        let bounds = [&[0.0, 1.0, 2.0][..], &[0.0, 0.5], &[0.0, 1.0]];
+        let mesh = mesh(Geometry::Spherical, bounds, &[(8.0, 0.1), (1.0, 0.2)]);
+        let resampled = resample(&mesh, &[vec![0.0, 2.0], vec![0.0, 0.5], vec![0.0, 1.0]]).unwrap();
+
+        // shells weighted by r^3, i.e. 1 and 7
+        let voxel = &resampled.voxels[0];
+        assert_close(voxel.result, (8.0 + 7.0) / 8.0);
+        assert_close(
+            voxel.error,
+            (0.8_f64.powi(2) + 1.4_f64.powi(2)).sqrt() / 15.0,
+        );
+    }
+
+    #[test]
+    fn resample_spherical_polar() {
+        let bounds = [&[0.0, 1.0][..], &[0.0, 0.25, 0.5], &[0.0, 1.0]];
+        let mesh = mesh(Geometry::Spherical, bounds, &[(2.0, 0.1), (4.0, 0.1)]);
+        let resampled = resample(&mesh, &[vec![0.0, 1.0], vec![0.0, 0.5], vec![0.0, 1.0]]).unwrap();
+
+        // both hemispheres have the same solid angle
+        assert_close(resampled.voxels[0].result, 3.0);
+    }
+}
@@ -0,0 +1,58 @@
+//! Floating point comparisons shared by mesh and group checks
+//!
+//! Coordinates and group bounds are read back from text files written to a
+//! limited precision, so values that should be equal rarely are exactly.
+
+/// Relative tolerance for comparing coordinates and group bounds
+pub const TOLERANCE: f64 = 1e-6;
+
+/// Check two values match within the relative tolerance
+///
+/// The tolerance is relative to the larger magnitude, so very small values
+/// such as thermal energy bounds are still distinguished.
+pub fn is_close(a: f64, b: f64) -> bool {
+    a == b || (a - b).abs() <= TOLERANCE * a.abs().max(b.abs())
+}
+
+/// Check two sets of coordinates match within the tolerance
+///
+/// The tolerance is relative to the largest magnitude in either set, so a
+/// coordinate near zero is compared on the scale of the rest of the mesh. The
+/// scale is never less than 1 cm, so sets of near-zero values such as an
+/// origin or a director component written as 1e-9 are still equal to zero.
+pub fn all_close(a: &[f64], b: &[f64]) -> bool {
+    let scale = a.iter().chain(b).fold(1.0_f64, |m, x| m.max(x.abs()));
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(x, y)| x == y || (x - y).abs() <= TOLERANCE * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_close_relative() {
+        assert!(is_close(1e-5, 1e-5 * (1.0 + 1e-7)));
+        assert!(!is_close(1e-5, 1.1e-5));
+        assert!(!is_close(0.0, 1e-9));
+    }
+
+    #[test]
+    fn all_close_near_zero() {
+        assert!(all_close(&[0.0, 0.0, 0.0], &[1e-9, 0.0, 0.0]));
+        assert!(!all_close(&[0.0, 0.0, 0.0], &[1e-3, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn all_close_relative() {
+        assert!(all_close(&[0.0, 1000.0], &[1e-4, 1000.0005]));
+        assert!(!all_close(&[0.0, 1000.0], &[0.01, 1000.0]));
+    }
+
+    #[test]
+    fn all_close_length() {
+        assert!(!all_close(&[0.0, 1.0], &[0.0]));
+    }
+}
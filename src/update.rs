@@ -1,21 +1,79 @@
+//! Iterative updating of weights from previous runs
+
 // standard library
 use std::fs::File;
 use std::io::BufReader;
 use std::io::BufWriter;
 use std::path::Path;
 
+// internal modules
+use crate::geometry;
+
+// neutronics toolbox
+use ntools::mesh::Particle;
+use ntools::utils::f;
+use ntools::weights::WeightWindow;
+
 // external crates
 use anyhow::Result;
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 
 #[repr(C)]
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct CachedWeights {
     pub particle: u8,
+    pub grid: CachedGrid,
     pub weight_error: Vec<(f64, f64)>,
 }
 
-/// Generate a binary file for the cached weights
+/// Mesh geometry and energy/time groups the cached weights belong to
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct CachedGrid {
+    pub nwg: u8,
+    pub origin: [f64; 3],
+    pub director_1: [f64; 3],
+    pub director_2: [f64; 3],
+    pub fine: [usize; 3],
+    pub coarse: [Vec<[f64; 3]>; 3],
+    pub e: Vec<f64>,
+    pub t: Vec<f64>,
+}
+
+impl CachedGrid {
+    /// Geometry and groups of a weight window
+    pub fn from_ww(ww: &WeightWindow) -> Self {
+        Self {
+            nwg: ww.nwg,
+            origin: ww.origin,
+            director_1: ww.director_1,
+            director_2: ww.director_2,
+            fine: [ww.nfx, ww.nfy, ww.nfz],
+            coarse: [ww.qps_x.clone(), ww.qps_y.clone(), ww.qps_z.clone()],
+            e: ww.e.clone(),
+            t: ww.t.clone(),
+        }
+    }
+
+    /// Every difference from the grid of a weight window
+    fn differences(&self, ww: &WeightWindow) -> Vec<String> {
+        let mut cached = ww.clone();
+        cached.nwg = self.nwg;
+        cached.origin = self.origin;
+        cached.director_1 = self.director_1;
+        cached.director_2 = self.director_2;
+        [cached.nfx, cached.nfy, cached.nfz] = self.fine;
+        [cached.qps_x, cached.qps_y, cached.qps_z] = self.coarse.clone();
+        cached.ncx = cached.qps_x.len();
+        cached.ncy = cached.qps_y.len();
+        cached.ncz = cached.qps_z.len();
+        cached.e = self.e.clone();
+        cached.t = self.t.clone();
+        geometry::grid_differences(&cached, ww)
+    }
+}
+
+/// Read cached weights from a binary file
 pub fn read_binary(path: impl AsRef<Path>) -> Result<CachedWeights> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
@@ -28,17 +86,115 @@ pub fn write_binary(path: impl AsRef<Path>, cached: &CachedWeights) -> Result<()
     Ok(bincode::serialize_into(f, cached)?)
 }
 
-// pub fn prefetch_binary<P: AsRef<Path>>(path: P, rad_type: RadType) -> Result<()> {
-//     let weight_error = zip(&weight_window.weights, &mesh.voxels)
-//         .map(|(w, v)| (*w, v.error))
-//         .collect();
-
-//     serialize_into(
-//         &mut f,
-//         &CachedWeights {
-//             particle: weight_window.particle,
-//             weight_error,
-//         },
-//     )
-//     .expect("Failed to serialize cached weights to binary");
-// }
+/// Cache file name for a particle type, e.g. "cached_weights_neutron"
+pub fn cache_path(prefix: &str, particle: u8) -> String {
+    f!(
+        "{prefix}_{}",
+        f!("{:?}", Particle::from_id(particle)).to_lowercase()
+    )
+}
+
+/// Merge new weights with any cached from previous runs and update the cache
+///
+/// Every voxel keeps whichever weight is better converged. Analogue (zero)
+/// weights never replace a non-zero weight, otherwise the lowest relative
+/// error wins. The merged weights and errors are written back to the cache,
+/// and the merged errors returned.
+///
+/// A cache for a different particle, mesh geometry, or energy/time groups is
+/// never merged, and is overwritten with a warning listing the differences.
+pub fn update_weights(ww: &mut WeightWindow, errors: &[f64], prefix: &str) -> Result<Vec<f64>> {
+    let path = cache_path(prefix, ww.particle);
+
+    let mut weight_error: Vec<(f64, f64)> = ww
+        .weights
+        .iter()
+        .zip(errors)
+        .map(|(w, e)| (*w, *e))
+        .collect();
+
+    if Path::new(&path).exists() {
+        debug!("Reading cached weights from \"{path}\"");
+        match read_binary(&path) {
+            Ok(cached) => merge_cached(&cached, ww, &mut weight_error, &path),
+            Err(e) => {
+                warn!("Warning: Unable to read cached weights in \"{path}\": {e}");
+                warn!(" - Overwriting the cache with the current weights");
+            }
+        }
+    } else {
+        info!("No cached weights found, creating \"{path}\"");
+    }
+
+    ww.weights = weight_error.iter().map(|(w, _)| *w).collect();
+    let errors = weight_error.iter().map(|(_, e)| *e).collect();
+
+    debug!("Writing cached weights to \"{path}\"");
+    write_binary(
+        &path,
+        &CachedWeights {
+            particle: ww.particle,
+            grid: CachedGrid::from_ww(ww),
+            weight_error,
+        },
+    )?;
+
+    Ok(errors)
+}
+
+/// Keep the better converged of the cached and new (weight, error) pairs
+fn merge_cached(
+    cached: &CachedWeights,
+    ww: &WeightWindow,
+    weight_error: &mut [(f64, f64)],
+    path: &str,
+) {
+    let mut differences = cached.grid.differences(ww);
+    if cached.particle != ww.particle {
+        differences.insert(
+            0,
+            f!(
+                "particle: {:?} vs {:?}",
+                Particle::from_id(cached.particle),
+                Particle::from_id(ww.particle)
+            ),
+        );
+    }
+    if differences.is_empty() && cached.weight_error.len() != weight_error.len() {
+        differences.push(f!(
+            "number of weights: {} vs {}",
+            cached.weight_error.len(),
+            weight_error.len()
+        ));
+    }
+
+    if !differences.is_empty() {
+        warn!("Warning: Cached weights in \"{path}\" do not match this mesh");
+        for d in differences {
+            warn!(" - {d}");
+        }
+        warn!(" - Overwriting the cache with the current weights");
+        return;
+    }
+
+    let mut n_updated = 0;
+    for (new, old) in weight_error.iter_mut().zip(&cached.weight_error) {
+        if is_better_converged(old, new) {
+            *new = *old;
+            n_updated += 1;
+        }
+    }
+    info!(
+        "Kept {n_updated} of {} cached weights",
+        cached.weight_error.len()
+    );
+}
+
+/// Check if the cached (weight, error) pair is better than the new one
+fn is_better_converged(cached: &(f64, f64), new: &(f64, f64)) -> bool {
+    match (cached.0 > 0.0, new.0 > 0.0) {
+        (true, false) => true,
+        (true, true) => cached.1 < new.1,
+        _ => false,
+    }
+}
@@ -1,8 +1,13 @@
 //! Basic wrappers for external crate types
 
+use serde::{Deserialize, Serialize};
+
 // Wrapper for ntools VTK format variants
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
 pub enum CliVtkFormat {
     Xml,
     LegacyAscii,
@@ -20,7 +25,10 @@ impl From<CliVtkFormat> for ntools::weights::vtk::VtkFormat {
 }
 
 // Wrapper for byte order used by vtkio
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
 pub enum CliByteOrder {
     BigEndian,
     LittleEndian,
@@ -37,9 +45,13 @@ impl From<CliByteOrder> for vtkio::model::ByteOrder {
 
 // Wrapper for compression strategy used by vtkio
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum CliCompressor {
     LZ4,
+    #[value(name = "zlib")]
     ZLib,
     LZMA,
     None,
@@ -0,0 +1,409 @@
+//! Reading of existing WWINP files for post-processing
+//!
+//! Files follow the WWINP specification from the MCNP user manuals, whether
+//! written by the MCNP weight window generator or by previous runs.
+
+use crate::config::WWConfig;
+
+use ntools::mesh::Particle;
+use ntools::weights::WeightWindow;
+
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Read every particle type from a WWINP file
+///
+/// Particles with no energy groups (i.e. padding in the header) are skipped.
+/// Weights are reordered from the file layout (time, energy, then k, j, i)
+/// into the energy-major order used by [WeightWindow].
+pub fn read_wwinp(path: impl AsRef<Path>) -> Result<Vec<WeightWindow>> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Unable to read \"{}\": {e}", path.display()))?;
+    parse_wwinp(&content).map_err(|e| anyhow!("Invalid WWINP \"{}\": {e}", path.display()))
+}
+
+/// Read every particle type, counting any values left after the last weight
+///
+/// Unlike [read_wwinp], values after the last weight are not an error, so that
+/// they can be reported alongside any other problems with the file.
+pub(crate) fn read_wwinp_trailing(path: impl AsRef<Path>) -> Result<(Vec<WeightWindow>, usize)> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Unable to read \"{}\": {e}", path.display()))?;
+    parse_weights(&content).map_err(|e| anyhow!("Invalid WWINP \"{}\": {e}", path.display()))
+}
+
+/// Check if a file looks like a WWINP file from the first line
+pub fn is_wwinp(path: impl AsRef<Path>) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+
+    let mut line = String::new();
+    if BufReader::new(file).read_line(&mut line).is_err() {
+        return false;
+    }
+
+    match parse_header(&line) {
+        Ok((f, iv, _, nr, _)) => f == 1 && (iv == 1 || iv == 2) && (nr == 10 || nr == 16),
+        Err(_) => false,
+    }
+}
+
+/// Replace or add particles in the weight windows of an existing file
+///
+/// Particles in the file without new weights are kept as they are, and the
+/// combined list is ordered by particle id. If the file does not exist yet,
+/// the new weight windows are returned unchanged.
+pub fn append_to_file(
+    weight_windows: Vec<WeightWindow>,
+    path: impl AsRef<Path>,
+) -> Result<Vec<WeightWindow>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        warn!(
+            "Warning: \"{}\" does not exist, writing a new file",
+            path.display()
+        );
+        return Ok(weight_windows);
+    }
+
+    let mut combined = read_wwinp(path)?;
+    for ww in weight_windows {
+        let particle = Particle::from_id(ww.particle);
+        match combined.iter_mut().find(|w| w.particle == ww.particle) {
+            Some(existing) => {
+                info!("Replacing {particle:?} weights in \"{}\"", path.display());
+                *existing = ww;
+            }
+            None => {
+                info!("Adding {particle:?} weights to \"{}\"", path.display());
+                combined.push(ww);
+            }
+        }
+    }
+
+    combined.sort_by_key(|ww| ww.particle);
+    Ok(combined)
+}
+
+/// Check a set does not use options that need a mesh tally
+pub fn validate_wwinp_set(cli: &WWConfig) -> Result<()> {
+    let default = WWConfig::default();
+
+    let unused = [
+        ("--merge", cli.merge != default.merge),
+        ("--method", cli.method != default.method),
+        ("--power", cli.power != default.power),
+        ("--auto-power", cli.auto_power != default.auto_power),
+        ("--error", cli.error != default.error),
+        ("--coverage", cli.coverage != default.coverage),
+        ("--total", cli.total != default.total),
+        ("--ww-energies", cli.ww_energies != default.ww_energies),
+        ("--ww-times", cli.ww_times != default.ww_times),
+        ("--update", cli.update != default.update),
+        ("--source", cli.source != default.source),
+        ("--response", cli.response != default.response),
+    ]
+    .into_iter()
+    .filter_map(|(option, is_set)| is_set.then_some(option))
+    .collect::<Vec<&str>>();
+
+    if unused.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Unable to use {} with a WWINP input, there is no mesh tally",
+            unused.join(", ")
+        ))
+    }
+}
+
+/// Split the first line into (if, iv, ni, nr, probid)
+fn parse_header(line: &str) -> Result<(u8, u8, u8, u8, String)> {
+    let mut tokens = line.split_whitespace();
+    let mut next = || -> Result<u8> {
+        tokens
+            .next()
+            .ok_or_else(|| anyhow!("Incomplete header"))?
+            .parse::<u8>()
+            .map_err(|_| anyhow!("Invalid header"))
+    };
+
+    let (f, iv, ni, nr) = (next()?, next()?, next()?, next()?);
+    let probid = line.get(60..).unwrap_or_default().trim().to_string();
+    Ok((f, iv, ni, nr, probid))
+}
+
+/// Header of a WWINP file, including any padding entries
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    /// File type, always 1
+    pub f: u8,
+    /// Time-dependent windows if 2, otherwise 1
+    pub iv: u8,
+    /// Number of particle entries
+    pub ni: u8,
+    /// Number of mesh header values, 10 or 16
+    pub nr: u8,
+    /// Problem identification string
+    pub probid: String,
+    /// Time groups of every particle entry
+    pub nt: Vec<usize>,
+    /// Energy groups of every particle entry, zero for padding
+    pub ne: Vec<usize>,
+}
+
+/// Read the header of a WWINP file, without the mesh or weights
+pub fn read_header(path: impl AsRef<Path>) -> Result<Header> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Unable to read \"{}\": {e}", path.display()))?;
+    parse_particle_header(&content)
+        .map(|(header, _)| header)
+        .map_err(|e| anyhow!("Invalid WWINP \"{}\": {e}", path.display()))
+}
+
+/// Parse the header and particle groups, leaving the rest as a number stream
+fn parse_particle_header(
+    content: &str,
+) -> Result<(Header, impl Iterator<Item = Result<f64>> + '_)> {
+    let (header, body) = content.split_once('\n').unwrap_or((content, ""));
+    let (f, iv, ni, nr, probid) = parse_header(header)?;
+
+    // everything else is a stream of numbers
+    let mut values = body.split_whitespace().map(|v| {
+        v.parse::<f64>()
+            .map_err(|_| anyhow!("Invalid value \"{v}\""))
+    });
+    let mut next = || -> Result<f64> {
+        values
+            .next()
+            .ok_or_else(|| anyhow!("Unexpected end of file"))?
+    };
+
+    // block 1, particle groups
+    let nt = (0..ni)
+        .map(|_| if iv == 2 { Ok(next()? as usize) } else { Ok(1) })
+        .collect::<Result<Vec<usize>>>()?;
+    let ne = (0..ni)
+        .map(|_| Ok(next()? as usize))
+        .collect::<Result<Vec<usize>>>()?;
+
+    let header = Header {
+        f,
+        iv,
+        ni,
+        nr,
+        probid,
+        nt,
+        ne,
+    };
+    Ok((header, values))
+}
+
+fn parse_wwinp(content: &str) -> Result<Vec<WeightWindow>> {
+    match parse_weights(content)? {
+        (weight_windows, 0) => Ok(weight_windows),
+        (_, n) => Err(anyhow!("{n} unexpected values after the last weight")),
+    }
+}
+
+/// Parse every particle type, with the number of values left over
+fn parse_weights(content: &str) -> Result<(Vec<WeightWindow>, usize)> {
+    let (header, mut values) = parse_particle_header(content)?;
+    let Header {
+        f,
+        iv,
+        ni,
+        nr,
+        probid,
+        nt,
+        ne,
+    } = header;
+
+    let mut next = || -> Result<f64> {
+        values
+            .next()
+            .ok_or_else(|| anyhow!("Unexpected end of file"))?
+    };
+
+    // block 1, mesh header
+    let (nfx, nfy, nfz) = (next()? as usize, next()? as usize, next()? as usize);
+    let origin = [next()?, next()?, next()?];
+    let (ncx, ncy, ncz) = (next()? as usize, next()? as usize, next()? as usize);
+
+    let (director_1, director_2) = match nr {
+        10 => ([0.0; 3], [0.0; 3]),
+        16 => ([next()?, next()?, next()?], [next()?, next()?, next()?]),
+        _ => return Err(anyhow!("Unknown header length nr={nr}")),
+    };
+    let nwg = next()? as u8;
+
+    // block 2, coarse mesh positions after the leading origin value
+    let mut coarse = |n: usize| -> Result<Vec<[f64; 3]>> {
+        next()?;
+        (0..n).map(|_| Ok([next()?, next()?, next()?])).collect()
+    };
+    let qps_x = coarse(ncx)?;
+    let qps_y = coarse(ncy)?;
+    let qps_z = coarse(ncz)?;
+
+    // block 3, groups and weights for every particle present
+    let n_voxels = nfx * nfy * nfz;
+    let mut weight_windows = Vec::new();
+
+    for (idx, (nt, ne)) in nt.into_iter().zip(ne).enumerate() {
+        if ne == 0 {
+            continue;
+        }
+
+        let t = if nt > 1 {
+            (0..nt).map(|_| next()).collect::<Result<Vec<f64>>>()?
+        } else {
+            Vec::new()
+        };
+        let e = (0..ne).map(|_| next()).collect::<Result<Vec<f64>>>()?;
+
+        let file_order = (0..nt * ne * n_voxels)
+            .map(|_| next())
+            .collect::<Result<Vec<f64>>>()?;
+
+        let mut weights = vec![0.0; file_order.len()];
+        for ti in 0..nt {
+            for ei in 0..ne {
+                let from = (ti * ne + ei) * n_voxels;
+                let to = (ei * nt + ti) * n_voxels;
+                weights[to..to + n_voxels].copy_from_slice(&file_order[from..from + n_voxels]);
+            }
+        }
+
+        debug!(
+            "Read particle {} with {ne} energy and {nt} time groups",
+            idx + 1
+        );
+        weight_windows.push(WeightWindow {
+            f,
+            iv,
+            ni,
+            nr,
+            probid: probid.clone(),
+            nt,
+            ne,
+            nfx,
+            nfy,
+            nfz,
+            origin,
+            ncx,
+            ncy,
+            ncz,
+            director_1,
+            director_2,
+            nwg,
+            qps_x: qps_x.clone(),
+            qps_y: qps_y.clone(),
+            qps_z: qps_z.clone(),
+            e,
+            t,
+            weights,
+            particle: idx as u8 + 1,
+        });
+    }
+
+    if weight_windows.is_empty() {
+        Err(anyhow!("No particles with weights"))
+    } else {
+        Ok((weight_windows, values.count()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rectangular 2x1x1 mesh, two neutron energy groups and photon padding
+    const WWINP: &str =
+        "         1         1         2        10                     06/01/24 12:00:00
+         2         0
+       2.0000       1.0000       1.0000      -10.000      -5.000       0.0000
+       1.0000       1.0000       1.0000       1.0000
+      -10.000       2.0000       10.000       1.0000
+      -5.000       1.0000       5.0000       1.0000
+       0.0000       1.0000       5.0000       1.0000
+       1.0000       100.00
+       0.1000       0.2000       0.3000       0.4000
+";
+
+    /// Single voxel with two time and two energy groups
+    const WWINP_TIME: &str = "1 2 1 10
+2
+2
+1 1 1 0.0 0.0 0.0
+1 1 1 1
+0.0 1.0 1.0 1.0
+0.0 1.0 1.0 1.0
+0.0 1.0 1.0 1.0
+10.0 100.0
+1.0 20.0
+1.0 2.0 3.0 4.0
+";
+
+    #[test]
+    fn parse_wwinp_rectangular() {
+        let weight_windows = parse_wwinp(WWINP).unwrap();
+        assert_eq!(weight_windows.len(), 1);
+
+        let ww = &weight_windows[0];
+        assert_eq!(ww.particle, 1);
+        assert_eq!((ww.ni, ww.nr, ww.nwg), (2, 10, 1));
+        assert_eq!((ww.nfx, ww.nfy, ww.nfz), (2, 1, 1));
+        assert_eq!(ww.origin, [-10.0, -5.0, 0.0]);
+        assert_eq!(ww.qps_x, vec![[2.0, 10.0, 1.0]]);
+        assert_eq!(ww.qps_z, vec![[1.0, 5.0, 1.0]]);
+        assert_eq!(ww.e, vec![1.0, 100.0]);
+        assert!(ww.t.is_empty());
+        assert_eq!(ww.weights, vec![0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn parse_wwinp_time_order() {
+        let ww = &parse_wwinp(WWINP_TIME).unwrap()[0];
+        assert_eq!((ww.ne, ww.nt), (2, 2));
+        assert_eq!(ww.t, vec![10.0, 100.0]);
+        assert_eq!(ww.e, vec![1.0, 20.0]);
+        // file is time-major, weights are energy-major
+        assert_eq!(ww.weights, vec![1.0, 3.0, 2.0, 4.0]);
+    }
+
+    #[test]
+    fn parse_wwinp_truncated() {
+        let truncated = WWINP.trim_end().trim_end_matches("0.4000");
+        let err = parse_wwinp(truncated).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected end of file");
+    }
+
+    #[test]
+    fn parse_wwinp_trailing_values() {
+        let content = format!("{WWINP} 0.5 0.6\n");
+        let err = parse_wwinp(&content).unwrap_err();
+        assert_eq!(err.to_string(), "2 unexpected values after the last weight");
+        assert_eq!(parse_weights(&content).unwrap().1, 2);
+    }
+
+    #[test]
+    fn parse_wwinp_invalid_value() {
+        let content = WWINP.replace("0.3000", "0.3O00");
+        assert!(parse_wwinp(&content).is_err());
+    }
+
+    #[test]
+    fn parse_wwinp_only_padding() {
+        let content = WWINP.replacen("         2         0", "         0         0", 1);
+        let err = parse_wwinp(&content).unwrap_err();
+        assert_eq!(err.to_string(), "No particles with weights");
+    }
+}